@@ -0,0 +1,78 @@
+//! Compare `IpfArchive::extract_to` (serial) with `IpfArchive::extract_all` (parallel)
+//!
+//! Run with `cargo bench --bench extract`
+
+use std::{
+    fs::{self, File},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use libtos::{ExtractOptions, IpfArchive, IpfWriter};
+
+const ENTRIES: usize = 2000;
+const ENTRY_SIZE: usize = 64 * 1024;
+
+fn write_archive(path: &Path) {
+    let mut writer = IpfWriter::new(File::create(path).unwrap());
+    let mut state = 0x2545_F491u32;
+    for index in 0..ENTRIES {
+        // text-like data which deflates to about a third
+        let content: Vec<u8> = (0..ENTRY_SIZE)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                b"abcdefgh ,\n"[(state % 11) as usize]
+            })
+            .collect();
+        writer
+            .add_bytes(
+                "bench.ipf",
+                format!("dir{}/{index}.xml", index % 16),
+                content,
+            )
+            .unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+/// Best of three runs
+fn time(out: &Path, mut extract: impl FnMut()) -> Duration {
+    (0..3)
+        .map(|_| {
+            let _ = fs::remove_dir_all(out);
+            let start = Instant::now();
+            extract();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join(format!("libtos-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("bench.ipf");
+    write_archive(&path);
+    let out = dir.join("out");
+
+    let serial = time(&out, || {
+        IpfArchive::open(&path).unwrap().extract_to(&out).unwrap();
+    });
+    let parallel = time(&out, || {
+        let report = IpfArchive::open(&path)
+            .unwrap()
+            .extract_all(&out, &ExtractOptions::default());
+        assert!(report.is_ok());
+    });
+
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    println!("{ENTRIES} entries of {} KiB", ENTRY_SIZE / 1024);
+    println!("extract_to:  {serial:?}");
+    println!(
+        "extract_all: {parallel:?} on {threads} threads ({:.1}x)",
+        serial.as_secs_f64() / parallel.as_secs_f64()
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,13 @@
+use libtos::{IpfError, IpfWriter};
+
+fn main() -> Result<(), IpfError> {
+    let file = std::fs::File::create("path/to/new.ipf")?;
+    let mut ipf = IpfWriter::new(file);
+
+    ipf.add_bytes("example.ipf", "hello.txt", "Hello, Savior!")?;
+    let image = std::fs::File::open("path/to/event1234.jpg")?;
+    ipf.add_entry("example.ipf", "event_banner/event1234.jpg", image)?;
+
+    ipf.finish()?;
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use libtos::{ies::IesTable, IpfArchive, IpfError, ReadMode};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Item {
+    #[serde(rename = "ClassID")]
+    class_id: u32,
+    class_name: String,
+    #[serde(rename = "UseLv")]
+    level: u32,
+}
+
+fn main() -> Result<(), IpfError> {
+    let mut ipf = IpfArchive::open("path/to/ies.ipf")?;
+    let mut ies = Vec::new();
+    for entry in ipf.entries() {
+        if entry.file_name().as_os_str() == "item.ies" {
+            ies.push(entry.index());
+        }
+    }
+
+    for index in ies {
+        let mut entry = ipf.by_index_with(index, ReadMode::Decompressed)?;
+        let mut buffer = Vec::new();
+        std::io::copy(&mut entry, &mut buffer)?;
+
+        let table = IesTable::parse(std::io::Cursor::new(buffer))?;
+        for item in table.deserialize::<Item>()? {
+            println!("{} {}: Lv. {}", item.class_id, item.class_name, item.level);
+        }
+    }
+    Ok(())
+}
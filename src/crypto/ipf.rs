@@ -1,30 +1,35 @@
 use std::io;
 use std::num::Wrapping;
 
-const PASSWORD: [u8; 20] = [
-    0x6F, 0x66, 0x4F, 0x31, 0x61, 0x30, 0x75, 0x65, 0x58, 0x41, 0x3F, 0x20, 0x5B, 0xFF, 0x73, 0x20,
-    0x68, 0x20, 0x25, 0x3F,
-];
+use super::CryptoProfile;
 
-struct ZipCrypto {
+#[derive(Clone)]
+pub(crate) struct ZipCrypto {
     key_0: Wrapping<u32>,
     key_1: Wrapping<u32>,
     key_2: Wrapping<u32>,
 }
 
 impl ZipCrypto {
-    fn new() -> Self {
-        let mut ret = Self {
-            key_0: Wrapping(0x12345678),
-            key_1: Wrapping(0x23456789),
-            key_2: Wrapping(0x34567890),
-        };
+    /// keys after initializing with `password`
+    pub(crate) fn from_password(password: &[u8]) -> Self {
+        let mut ret = Self::from_keys([0x12345678, 0x23456789, 0x34567890]);
+        for password in password {
+            Self::update_key(&mut ret, *password)
+        }
+        ret
+    }
 
-        for password in PASSWORD {
-            Self::update_key(&mut ret, password)
+    pub(crate) fn from_keys(keys: [u32; 3]) -> Self {
+        Self {
+            key_0: Wrapping(keys[0]),
+            key_1: Wrapping(keys[1]),
+            key_2: Wrapping(keys[2]),
         }
+    }
 
-        ret
+    pub(crate) fn keys(&self) -> [u32; 3] {
+        [self.key_0.0, self.key_1.0, self.key_2.0]
     }
 
     fn crc32(crc: Wrapping<u32>, char: u8) -> Wrapping<u32> {
@@ -43,7 +48,6 @@ impl ZipCrypto {
         ((temp * (temp ^ Wrapping(1))) >> 8).0 as u8
     }
 
-    #[allow(dead_code)]
     /// Encrypt a single byte
     fn encrypt_byte(&mut self, plain_byte: u8) -> u8 {
         let cipher_byte = self.stream_byte() ^ plain_byte;
@@ -52,41 +56,145 @@ impl ZipCrypto {
     }
 
     /// Decrypt a single byte
-    fn decrypt_byte(&mut self, cipher_byte: u8) -> u8 {
+    pub(super) fn decrypt_byte(&mut self, cipher_byte: u8) -> u8 {
         let plain_byte = self.stream_byte() ^ cipher_byte;
         self.update_key(plain_byte);
         plain_byte
     }
 }
 
-pub(crate) struct IpfCrypto<R: io::Read> {
+/// Reader which decrypts data of an ipf entry, e.g. `ReadMode::Raw` into `ReadMode::Decrypted`
+///
+/// Every `stride`th byte of the whole stream is decrypted as `CryptoProfile` sets, counting from
+/// the first byte read, so `reader` has to be at the start of the data. The output is still deflated.
+pub struct IpfDecryptReader<R: io::Read> {
     reader: R,
-    keys: ZipCrypto,
+    keys: Option<ZipCrypto>,
+    stride: u64,
+    position: u64,
 }
 
-impl<R: io::Read> IpfCrypto<R> {
-    pub fn new(reader: R) -> Self {
-        IpfCrypto {
+impl<R: io::Read> IpfDecryptReader<R> {
+    /// Decrypt data read from `reader` with `profile`, e.g. `CryptoProfile::default()`
+    pub fn new(reader: R, profile: &CryptoProfile) -> Self {
+        Self::with_crypto(reader, Some(profile))
+    }
+
+    /// `profile` is None for entries which are not encrypted
+    pub(crate) fn with_crypto(reader: R, profile: Option<&CryptoProfile>) -> Self {
+        IpfDecryptReader {
             reader,
-            keys: ZipCrypto::new(),
+            keys: profile.and_then(CryptoProfile::zip_crypto),
+            stride: profile.map_or(1, |profile| profile.stride().into()),
+            position: 0,
         }
     }
+
+    /// Get a reference to the inner reader
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Get a mutable reference to the inner reader, reading from it breaks decryption
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Unwrap the inner reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
 }
 
-impl<R: io::Read> io::Read for IpfCrypto<R> {
+impl<R: io::Read> io::Read for IpfDecryptReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let result = self.reader.read(buf);
-        for (index, byte) in buf.iter_mut().enumerate() {
-            if index % 2 == 0 {
-                *byte = self.keys.decrypt_byte(*byte);
+        let read = self.reader.read(buf)?;
+        let keys = match &mut self.keys {
+            Some(keys) => keys,
+            None => return Ok(read),
+        };
+        // only every other byte of the whole stream is encrypted (with stride 2)
+        for byte in &mut buf[..read] {
+            if self.position.is_multiple_of(self.stride) {
+                *byte = keys.decrypt_byte(*byte);
+            }
+            self.position += 1;
+        }
+        Ok(read)
+    }
+}
+
+/// Writer which encrypts deflated data into data of an ipf entry, see `IpfDecryptReader`
+///
+/// Every `stride`th byte of the whole stream is encrypted as `CryptoProfile` sets,
+/// counting from the first byte written.
+pub struct IpfEncryptWriter<W: io::Write> {
+    writer: W,
+    keys: Option<ZipCrypto>,
+    stride: u64,
+    position: u64,
+    buffer: Vec<u8>,
+}
+
+impl<W: io::Write> IpfEncryptWriter<W> {
+    /// Encrypt data written to `writer` with `profile`, e.g. `CryptoProfile::default()`
+    pub fn new(writer: W, profile: &CryptoProfile) -> Self {
+        Self::with_crypto(writer, Some(profile))
+    }
+
+    /// `profile` is None for entries which are not encrypted
+    pub(crate) fn with_crypto(writer: W, profile: Option<&CryptoProfile>) -> Self {
+        IpfEncryptWriter {
+            writer,
+            keys: profile.and_then(CryptoProfile::zip_crypto),
+            stride: profile.map_or(1, |profile| profile.stride().into()),
+            position: 0,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Get a reference to the inner writer
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Get a mutable reference to the inner writer, writing to it breaks encryption
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Unwrap the inner writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: io::Write> io::Write for IpfEncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let keys = match &mut self.keys {
+            Some(keys) => keys,
+            None => return self.writer.write(buf),
+        };
+        self.buffer.clear();
+        self.buffer.extend_from_slice(buf);
+        // only every other byte of the whole stream is encrypted (with stride 2)
+        for byte in self.buffer.iter_mut() {
+            if self.position.is_multiple_of(self.stride) {
+                *byte = keys.encrypt_byte(*byte);
             }
+            self.position += 1;
         }
-        result
+        self.writer.write_all(&self.buffer)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
     }
 }
 
 /// precalculated crc32 table
-const CRC_TABLE: [u32; 256] = [
+pub(super) const CRC_TABLE: [u32; 256] = [
     0x00000000, 0x77073096, 0xEE0E612C, 0x990951BA, 0x076DC419, 0x706AF48F, 0xE963A535, 0x9E6495A3,
     0x0EDB8832, 0x79DCB8A4, 0xE0D5E91E, 0x97D2D988, 0x09B64C2B, 0x7EB17CBD, 0xE7B82D07, 0x90BF1D91,
     0x1DB71064, 0x6AB020F2, 0xF3B97148, 0x84BE41DE, 0x1ADAD47D, 0x6DDDE4EB, 0xF4D4B551, 0x83D385C7,
@@ -120,3 +228,118 @@ const CRC_TABLE: [u32; 256] = [
     0xBDBDF21C, 0xCABAC28A, 0x53B39330, 0x24B4A3A6, 0xBAD03605, 0xCDD70693, 0x54DE5729, 0x23D967BF,
     0xB3667A2E, 0xC4614AB8, 0x5D681B02, 0x2A6F2B94, 0xB40BBE37, 0xC30C8EA1, 0x5A05DF1B, 0x2D02EF8D,
 ];
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+    use proptest::{collection::vec, prelude::*};
+
+    use super::{IpfDecryptReader, IpfEncryptWriter};
+    use crate::crypto::CryptoProfile;
+
+    /// Returns at most the next of `sizes` bytes on each read, like a socket or a decoder would
+    struct Chunked<'a> {
+        data: &'a [u8],
+        sizes: Vec<usize>,
+        call: usize,
+    }
+
+    impl Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let size = self.sizes[self.call % self.sizes.len()];
+            self.call += 1;
+            let read = size.min(buf.len()).min(self.data.len());
+            buf[..read].copy_from_slice(&self.data[..read]);
+            self.data = &self.data[read..];
+            Ok(read)
+        }
+    }
+
+    fn encrypt(plain: &[u8], chunk: usize) -> Vec<u8> {
+        let mut cipher = Vec::new();
+        let mut writer = IpfEncryptWriter::new(&mut cipher, &CryptoProfile::default());
+        for chunk in plain.chunks(chunk) {
+            writer.write_all(chunk).unwrap();
+        }
+        cipher
+    }
+
+    /// `inner` are sizes the underlying reader returns, `outer` is the size of buffer to read into
+    fn decrypt(cipher: &[u8], inner: Vec<usize>, outer: usize) -> Vec<u8> {
+        let mut reader = IpfDecryptReader::new(
+            Chunked {
+                data: cipher,
+                sizes: inner,
+                call: 0,
+            },
+            &CryptoProfile::default(),
+        );
+        let mut plain = Vec::new();
+        let mut buffer = vec![0xAA; outer];
+        loop {
+            let read = reader.read(&mut buffer).unwrap();
+            if read == 0 {
+                return plain;
+            }
+            assert!(
+                buffer[read..].iter().all(|byte| *byte == 0xAA),
+                "bytes which were not read are changed"
+            );
+            plain.extend_from_slice(&buffer[..read]);
+            buffer[..read].fill(0xAA);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn decrypt_is_independent_of_chunking(
+            plain in vec(any::<u8>(), 0..4096),
+            inner in vec(1usize..64, 1..8),
+            outer in 1usize..8192,
+        ) {
+            let cipher = encrypt(&plain, plain.len().max(1));
+            prop_assert_eq!(&decrypt(&cipher, vec![1], 1), &plain);
+            prop_assert_eq!(&decrypt(&cipher, vec![cipher.len().max(1)], 8192), &plain);
+            prop_assert_eq!(&decrypt(&cipher, inner, outer), &plain);
+        }
+
+        #[test]
+        fn encrypt_is_independent_of_chunking(
+            plain in vec(any::<u8>(), 0..4096),
+            chunk in 1usize..4096,
+        ) {
+            prop_assert_eq!(encrypt(&plain, chunk), encrypt(&plain, 1));
+        }
+
+        #[test]
+        fn deflate_through_odd_reads(
+            plain in vec(0u8..8, 0..16384),
+            inner in vec(prop_oneof![Just(1usize), 2usize..17, 4095usize..4099], 1..4),
+        ) {
+            let mut cipher = Vec::new();
+            let crypto = IpfEncryptWriter::new(&mut cipher, &CryptoProfile::default());
+            let mut encoder = DeflateEncoder::new(crypto, Compression::default());
+            encoder.write_all(&plain).unwrap();
+            encoder.finish().unwrap();
+
+            let mut decoded = Vec::new();
+            let reader = Chunked { data: &cipher, sizes: inner, call: 0 };
+            DeflateDecoder::new(IpfDecryptReader::new(reader, &CryptoProfile::default()))
+                .read_to_end(&mut decoded)
+                .unwrap();
+            prop_assert_eq!(decoded, plain);
+        }
+    }
+
+    #[test]
+    fn large_reads_match_small_reads() {
+        let plain: Vec<u8> = (0..1 << 20).map(|i: u32| (i * 31 % 251) as u8).collect();
+        let cipher = encrypt(&plain, 65536);
+        let expected = decrypt(&cipher, vec![1], 1);
+        assert_eq!(expected, plain);
+        assert_eq!(decrypt(&cipher, vec![7, 13, 1], 4097), expected);
+        assert_eq!(decrypt(&cipher, vec![1 << 20], 1 << 20), expected);
+    }
+}
@@ -1,5 +1,6 @@
-mod ies;
 mod ipf;
+mod profile;
+mod recover;
 
-pub(crate) use ies::IesReader;
-pub(crate) use ipf::IpfCrypto;
+pub use ipf::{IpfDecryptReader, IpfEncryptWriter};
+pub use profile::CryptoProfile;
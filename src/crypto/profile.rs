@@ -0,0 +1,188 @@
+use std::io;
+
+use flate2::read::DeflateDecoder;
+
+use super::{
+    ipf::{IpfDecryptReader, ZipCrypto},
+    recover::{self, MIN_KNOWN},
+};
+use crate::{
+    entry::Method,
+    error::{IpfError, Result},
+};
+
+/// Password of Tree of Savior archives
+const TOS_PASSWORD: [u8; 20] = [
+    0x6F, 0x66, 0x4F, 0x31, 0x61, 0x30, 0x75, 0x65, 0x58, 0x41, 0x3F, 0x20, 0x5B, 0xFF, 0x73, 0x20,
+    0x68, 0x20, 0x25, 0x3F,
+];
+
+/// Extensions which Tree of Savior stores as is, without compression and encryption
+const TOS_STORED_EXTENSIONS: [&str; 3] = ["jpg", "fsb", "mp3"];
+
+/// How entries of an archive are encrypted and compressed
+///
+/// Entries are deflated and then encrypted with ZipCrypto, except for the extensions
+/// in `stored_extensions` which are stored as is. Only every `stride`th byte of
+/// the encrypted stream is encrypted, starting from the first one.
+///
+/// `tree_of_savior` (the default) is the scheme of Tree of Savior clients,
+/// other variants can be set up from it, e.g. with `with_password`.
+///
+/// There are no built-in profiles for Granado Espada or older Tree of Savior clients,
+/// as their passwords and schemes are not known here and have not been checked against
+/// real archives of them. Try `unencrypted`, or recover the keys with `recover_keys`
+/// from an entry of which a part is known.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CryptoProfile {
+    keys: Option<[u32; 3]>,
+    stride: u32,
+    stored_extensions: Vec<String>,
+}
+
+impl Default for CryptoProfile {
+    fn default() -> Self {
+        Self::tree_of_savior()
+    }
+}
+
+impl CryptoProfile {
+    /// Every other byte encrypted with the password of Tree of Savior,
+    /// jpg, fsb and mp3 files stored as is
+    pub fn tree_of_savior() -> Self {
+        Self {
+            keys: Some(ZipCrypto::from_password(&TOS_PASSWORD).keys()),
+            stride: 2,
+            stored_extensions: TOS_STORED_EXTENSIONS.map(String::from).to_vec(),
+        }
+    }
+
+    /// Same as `tree_of_savior` but without encryption, entries are only deflated
+    pub fn unencrypted() -> Self {
+        Self {
+            keys: None,
+            ..Self::tree_of_savior()
+        }
+    }
+
+    /// Encrypt with another password
+    pub fn with_password(self, password: impl AsRef<[u8]>) -> Self {
+        self.with_keys(ZipCrypto::from_password(password.as_ref()).keys())
+    }
+
+    /// Encrypt starting from internal keys of ZipCrypto (key0, key1, key2)
+    /// which are already initialized with a password, e.g. recovered ones
+    pub fn with_keys(self, keys: [u32; 3]) -> Self {
+        Self {
+            keys: Some(keys),
+            ..self
+        }
+    }
+
+    /// Encrypt every `stride`th byte, 1 for every byte and 2 for every other byte
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stride` is 0
+    pub fn with_stride(self, stride: u32) -> Self {
+        assert!(stride != 0, "stride must not be 0");
+        Self { stride, ..self }
+    }
+
+    /// Store files of these extensions (without the dot, case-insensitive) as is
+    pub fn with_stored_extensions(
+        self,
+        extensions: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self {
+            stored_extensions: extensions
+                .into_iter()
+                .map(|extension| extension.into().to_ascii_lowercase())
+                .collect(),
+            ..self
+        }
+    }
+
+    /// Recover keys of an unknown password from an entry of which a part is known
+    ///
+    /// `encrypted` is the whole data of an encrypted entry as stored in the archive, and
+    /// `plaintext` is its decrypted (still deflated) data from `offset`, e.g. read with
+    /// `ReadMode::Decrypted` from the same file in an older archive. `plaintext` has to cover
+    /// at least 12 encrypted bytes, 24 bytes with stride 2. It takes hours of CPU time with that
+    /// little, and still about half a minute of CPU time (release build) with a few kilobytes.
+    ///
+    /// Stride and stored extensions are kept from `self`.
+    pub fn recover_keys(self, encrypted: &[u8], plaintext: &[u8], offset: usize) -> Result<Self> {
+        if offset
+            .checked_add(plaintext.len())
+            .is_none_or(|end| end > encrypted.len())
+        {
+            return Err(IpfError::KeyRecovery("Plaintext is longer than the entry"));
+        }
+        let stride = self.stride as usize;
+        let cipher: Vec<u8> = encrypted.iter().step_by(stride).copied().collect();
+        let plain: Vec<u8> = plaintext
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| (offset + index).is_multiple_of(stride))
+            .map(|(_, byte)| *byte)
+            .collect();
+        if plain.len() < MIN_KNOWN {
+            return Err(IpfError::KeyRecovery(
+                "Plaintext has to cover at least 12 encrypted bytes",
+            ));
+        }
+
+        // keys which only fit the plaintext by chance fail to inflate the entry
+        let keys = recover::recover_keys(&cipher, &plain, offset.div_ceil(stride), |keys| {
+            let profile = self.clone().with_keys(keys);
+            let mut reader = DeflateDecoder::new(IpfDecryptReader::new(encrypted, &profile));
+            io::copy(&mut reader, &mut io::sink()).is_ok()
+        });
+        match keys {
+            Some(keys) => Ok(self.with_keys(keys)),
+            None => Err(IpfError::KeyRecovery("No keys fit the plaintext")),
+        }
+    }
+
+    /// Internal keys of ZipCrypto after initializing with the password, None if not encrypted
+    pub fn keys(&self) -> Option<[u32; 3]> {
+        self.keys
+    }
+
+    /// Every `stride`th byte is encrypted
+    pub fn stride(&self) -> u32 {
+        self.stride
+    }
+
+    /// Extensions of files which are stored as is
+    pub fn stored_extensions(&self) -> &[String] {
+        &self.stored_extensions
+    }
+
+    /// Whether compressed entries are encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.keys.is_some()
+    }
+
+    pub(crate) fn zip_crypto(&self) -> Option<ZipCrypto> {
+        self.keys.map(ZipCrypto::from_keys)
+    }
+
+    /// how an entry of `file_name` is stored
+    pub(crate) fn method(&self, file_name: &str) -> Method {
+        let extension = std::path::Path::new(file_name).extension();
+        match extension {
+            Some(extension)
+                if self
+                    .stored_extensions
+                    .iter()
+                    .any(|stored| extension.eq_ignore_ascii_case(stored)) =>
+            {
+                Method::Stored
+            }
+            _ if self.is_encrypted() => Method::Encrypted,
+            _ => Method::Deflated,
+        }
+    }
+}
@@ -0,0 +1,487 @@
+//! Biham–Kocher known plaintext attack on ZipCrypto
+//!
+//! Indices are of the encrypted bytes only, i.e. every `stride`th byte of an entry.
+//! Keys before byte `i` are (X, Y, Z)`i`, and the keystream byte `i` comes from Z`i`[2,16).
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+    thread,
+};
+
+use super::ipf::{ZipCrypto, CRC_TABLE};
+
+/// Bytes of keystream which the attack needs at least
+pub(crate) const MIN_KNOWN: usize = 12;
+
+/// Bytes of keystream which the candidates are explored over, the rest filters them
+const CONTIGUOUS: usize = 8;
+
+const MULT_INV: u32 = inverse(0x08088405);
+
+const MASK_2_32: u32 = 0xFFFF_FFFC;
+const MASK_10_32: u32 = 0xFFFF_FC00;
+const MASK_24_32: u32 = 0xFF00_0000;
+const MASK_26_32: u32 = 0xFC00_0000;
+const MAX_DIFF_0_24: u32 = 0x00FF_FFFF + 0xFF;
+const MAX_DIFF_0_26: u32 = 0x03FF_FFFF + 0xFF;
+
+/// Multiplicative inverse of an odd number modulo 2^32
+const fn inverse(value: u32) -> u32 {
+    let mut inverse = value;
+    let mut i = 0;
+    while i < 5 {
+        inverse = inverse.wrapping_mul(2u32.wrapping_sub(value.wrapping_mul(inverse)));
+        i += 1;
+    }
+    inverse
+}
+
+fn crc32(crc: u32, byte: u8) -> u32 {
+    (crc >> 8) ^ CRC_TABLE[((crc as u8) ^ byte) as usize]
+}
+
+fn msb(value: u32) -> u8 {
+    (value >> 24) as u8
+}
+
+fn stream_byte(key_2: u32) -> u8 {
+    let temp = key_2 | 3;
+    ((temp.wrapping_mul(temp ^ 1) >> 8) & 0xFF) as u8
+}
+
+struct Tables {
+    /// crc_inv[msb(CRC_TABLE[i])] is CRC_TABLE[i] << 8 ^ i
+    crc_inv: [u32; 256],
+    /// Z[2,16) values giving a keystream byte, at the keystream byte
+    z_2_16: Vec<Vec<u32>>,
+    /// same as `z_2_16`, at keystream byte << 6 | Z[10,16)
+    z_2_16_by_10_16: Vec<Vec<u32>>,
+    /// x of which msb(x * MULT_INV) is m, m + 1 or m + 2, at m
+    fiber_up: Vec<Vec<u8>>,
+    /// x of which msb(x * MULT_INV) is m, m - 1 or m - 2, at m
+    fiber_down: Vec<Vec<u8>>,
+}
+
+impl Tables {
+    fn new() -> Self {
+        let mut crc_inv = [0; 256];
+        for (i, crc) in CRC_TABLE.iter().enumerate() {
+            crc_inv[msb(*crc) as usize] = crc << 8 ^ i as u32;
+        }
+
+        let mut z_2_16 = vec![Vec::new(); 256];
+        let mut z_2_16_by_10_16 = vec![Vec::new(); 256 << 6];
+        for z in (0..1 << 16).step_by(4) {
+            let key = stream_byte(z) as usize;
+            z_2_16[key].push(z);
+            z_2_16_by_10_16[key << 6 | (z >> 10) as usize].push(z);
+        }
+
+        let mut fiber_up = vec![Vec::new(); 256];
+        let mut fiber_down = vec![Vec::new(); 256];
+        for x in 0..=255u8 {
+            let product = msb(u32::from(x).wrapping_mul(MULT_INV));
+            for diff in 0..3 {
+                fiber_up[product.wrapping_sub(diff) as usize].push(x);
+                fiber_down[product.wrapping_add(diff) as usize].push(x);
+            }
+        }
+
+        Self {
+            crc_inv,
+            z_2_16,
+            z_2_16_by_10_16,
+            fiber_up,
+            fiber_down,
+        }
+    }
+
+    fn crc32_inv(&self, crc: u32, byte: u8) -> u32 {
+        crc << 8 ^ self.crc_inv[msb(crc) as usize] ^ u32::from(byte)
+    }
+
+    /// Z`i-1`[10,32) from Z`i`[2,32)
+    fn z_10_32_before(&self, z: u32) -> u32 {
+        self.crc32_inv(z, 0) & MASK_10_32
+    }
+
+    /// Y`i`[24,32) from Z`i` and Z`i-1`
+    fn y_24_32(&self, z: u32, z_before: u32) -> u32 {
+        (self.crc32_inv(z, 0) ^ z_before) << 24
+    }
+
+    /// Z[2,16) values giving `key` which fit Z[10,16) of `z_10_32`
+    fn z_2_16(&self, key: u8, z_10_32: u32) -> &[u32] {
+        &self.z_2_16_by_10_16[(key as usize) << 6 | ((z_10_32 >> 10) & 0x3F) as usize]
+    }
+
+    /// Keys before `cipher_byte` from the keys after it, and the plain byte
+    fn step_back(&self, keys: &mut [u32; 3], cipher_byte: u8) -> u8 {
+        let [x, y, z] = *keys;
+        let z = self.crc32_inv(z, msb(y));
+        let y = y
+            .wrapping_sub(1)
+            .wrapping_mul(MULT_INV)
+            .wrapping_sub(x & 0xFF);
+        let plain_byte = cipher_byte ^ stream_byte(z);
+        *keys = [self.crc32_inv(x, plain_byte), y, z];
+        plain_byte
+    }
+}
+
+/// Recover the keys before the first byte of `cipher`
+///
+/// `plain` is known from `cipher[start]` and has at least `MIN_KNOWN` bytes.
+/// Keys which fit the whole `plain` are passed to `accept`, and the first accepted one is returned.
+pub(crate) fn recover_keys(
+    cipher: &[u8],
+    plain: &[u8],
+    start: usize,
+    accept: impl Fn([u32; 3]) -> bool + Sync,
+) -> Option<[u32; 3]> {
+    assert!(plain.len() >= MIN_KNOWN && start + plain.len() <= cipher.len());
+
+    let tables = Tables::new();
+    let keystream: Vec<u8> = plain
+        .iter()
+        .zip(&cipher[start..])
+        .map(|(plain, cipher)| plain ^ cipher)
+        .collect();
+    let (last, candidates) = reduce_z(&tables, &keystream);
+
+    let attack = Attack {
+        tables: &tables,
+        keystream: &keystream,
+        cipher,
+        plain,
+        start,
+        index: last + 1 - CONTIGUOUS,
+        accept: &accept,
+    };
+    let next = AtomicUsize::new(0);
+    let found = OnceLock::new();
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    thread::scope(|scope| {
+        for _ in 0..threads.min(candidates.len()) {
+            scope.spawn(|| {
+                let mut lists = Lists::default();
+                while found.get().is_none() {
+                    let Some(z) = candidates.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+                    if let Some(keys) = attack.carry_out(&mut lists, *z) {
+                        let _ = found.set(keys);
+                    }
+                }
+            });
+        }
+    });
+    found.into_inner()
+}
+
+/// Narrow down Z`i`[2,32) with the keystream, going back from the last byte
+///
+/// Returns the index with the fewest candidates, which is at least `CONTIGUOUS - 1`
+/// so the attack has enough bytes before it.
+fn reduce_z(tables: &Tables, keystream: &[u8]) -> (usize, Vec<u32>) {
+    let last = keystream.len() - 1;
+    let mut candidates: Vec<u32> = tables.z_2_16[keystream[last] as usize]
+        .iter()
+        .flat_map(|z_2_16| (0..1 << 16).map(move |high| high << 16 | z_2_16))
+        .collect();
+    let mut best = (last, candidates.clone());
+
+    for index in (CONTIGUOUS - 1..last).rev() {
+        let mut before = Vec::with_capacity(candidates.len());
+        for z in &candidates {
+            let z_10_32 = tables.z_10_32_before(*z);
+            for z_2_16 in tables.z_2_16(keystream[index], z_10_32) {
+                before.push(z_10_32 | z_2_16);
+            }
+        }
+        before.sort_unstable();
+        before.dedup();
+        candidates = before;
+
+        if candidates.len() < best.1.len() {
+            best = (index, candidates.clone());
+        }
+    }
+    best
+}
+
+/// Keys of the `CONTIGUOUS` bytes being explored
+#[derive(Default)]
+struct Lists {
+    x: [u32; CONTIGUOUS],
+    y: [u32; CONTIGUOUS],
+    z: [u32; CONTIGUOUS],
+}
+
+struct Attack<'a, F> {
+    tables: &'a Tables,
+    keystream: &'a [u8],
+    cipher: &'a [u8],
+    plain: &'a [u8],
+    start: usize,
+    /// index in the keystream of the first explored byte
+    index: usize,
+    accept: &'a F,
+}
+
+impl<F: Fn([u32; 3]) -> bool> Attack<'_, F> {
+    /// Try a candidate of Z[2,32) at the last explored byte
+    fn carry_out(&self, lists: &mut Lists, z: u32) -> Option<[u32; 3]> {
+        lists.z[CONTIGUOUS - 1] = z;
+        self.explore_z(lists, CONTIGUOUS - 1)
+    }
+
+    fn explore_z(&self, lists: &mut Lists, i: usize) -> Option<[u32; 3]> {
+        if i == 0 {
+            return self.explore_y7(lists);
+        }
+
+        let z_10_32 = self.tables.z_10_32_before(lists.z[i]);
+        for z_2_16 in self
+            .tables
+            .z_2_16(self.keystream[self.index + i - 1], z_10_32)
+        {
+            lists.z[i - 1] = z_10_32 | z_2_16;
+
+            // Z`i`[0,2) from Z`i-1`[8,10)
+            lists.z[i] &= MASK_2_32;
+            lists.z[i] |= (self.tables.crc32_inv(lists.z[i], 0) ^ lists.z[i - 1]) >> 8;
+
+            if i < CONTIGUOUS - 1 {
+                lists.y[i + 1] = self.tables.y_24_32(lists.z[i + 1], lists.z[i]);
+            }
+
+            if let Some(keys) = self.explore_z(lists, i - 1) {
+                return Some(keys);
+            }
+        }
+        None
+    }
+
+    /// Guess Y7[0,24), the Z list is complete and Y`i`[24,32) are known
+    fn explore_y7(&self, lists: &mut Lists) -> Option<[u32; 3]> {
+        let y7_24_32 = lists.y[7] & MASK_24_32;
+        let y6_24_32 = lists.y[6] & MASK_24_32;
+        // (Y7[8,32) - 1) * MULT_INV
+        let mut product =
+            (u32::from(msb(y7_24_32)).wrapping_mul(MULT_INV) << 24).wrapping_sub(MULT_INV);
+        for y7_8_24 in (0..1 << 24).step_by(1 << 8) {
+            // Y6 + LSB(X7) is (Y7 - 1) * MULT_INV
+            let low = y6_24_32.wrapping_sub(product);
+            for y7_0_8 in &self.tables.fiber_up[msb(low) as usize] {
+                if u32::from(*y7_0_8).wrapping_mul(MULT_INV).wrapping_sub(low) <= MAX_DIFF_0_24 {
+                    lists.y[7] = u32::from(*y7_0_8) | y7_8_24 | y7_24_32;
+                    if let Some(keys) = self.explore_y(lists, 7) {
+                        return Some(keys);
+                    }
+                }
+            }
+            product = product.wrapping_add(MULT_INV << 8);
+        }
+        None
+    }
+
+    /// Get Y`i-1` and LSB(X`i`) from Y`i`
+    fn explore_y(&self, lists: &mut Lists, i: usize) -> Option<[u32; 3]> {
+        if i == 3 {
+            return self.test_x(lists);
+        }
+
+        let fy = lists.y[i].wrapping_sub(1).wrapping_mul(MULT_INV);
+        let ffy = fy.wrapping_sub(1).wrapping_mul(MULT_INV);
+        let high = ffy.wrapping_sub(lists.y[i - 2] & MASK_24_32);
+        for x_0_8 in &self.tables.fiber_down[msb(high) as usize] {
+            let y_before = fy.wrapping_sub(u32::from(*x_0_8));
+            if high.wrapping_sub(u32::from(*x_0_8).wrapping_mul(MULT_INV)) <= MAX_DIFF_0_24
+                && msb(y_before) == msb(lists.y[i - 1])
+            {
+                lists.y[i - 1] = y_before;
+                lists.x[i] = u32::from(*x_0_8);
+                if let Some(keys) = self.explore_y(lists, i - 1) {
+                    return Some(keys);
+                }
+            }
+        }
+        None
+    }
+
+    /// Complete the X list from its LSBs and check the keys against the whole plaintext
+    fn test_x(&self, lists: &mut Lists) -> Option<[u32; 3]> {
+        let plain = &self.plain[self.index..];
+        for i in 5..CONTIGUOUS {
+            lists.x[i] = (crc32(lists.x[i - 1], plain[i - 1]) & !0xFF) | (lists.x[i] & 0xFF);
+        }
+
+        // X3 has to fit Y1[26,32)
+        let mut x = lists.x[7];
+        for i in (3..7).rev() {
+            x = self.tables.crc32_inv(x, plain[i]);
+        }
+        let y1_26_32 = self.tables.y_24_32(lists.z[1], lists.z[0]) & MASK_26_32;
+        let y2 = lists.y[3]
+            .wrapping_sub(1)
+            .wrapping_mul(MULT_INV)
+            .wrapping_sub(x & 0xFF);
+        if y2
+            .wrapping_sub(1)
+            .wrapping_mul(MULT_INV)
+            .wrapping_sub(y1_26_32)
+            > MAX_DIFF_0_26
+        {
+            return None;
+        }
+
+        let last = self.start + self.index + CONTIGUOUS - 1;
+        let keys = [lists.x[7], lists.y[7], lists.z[7]];
+
+        let mut forward = ZipCrypto::from_keys(keys);
+        for (cipher_byte, plain_byte) in self.cipher[last..]
+            .iter()
+            .zip(&self.plain[last - self.start..])
+        {
+            if forward.decrypt_byte(*cipher_byte) != *plain_byte {
+                return None;
+            }
+        }
+
+        let mut backward = keys;
+        for position in (0..last).rev() {
+            let plain_byte = self.tables.step_back(&mut backward, self.cipher[position]);
+            if position >= self.start && plain_byte != self.plain[position - self.start] {
+                return None;
+            }
+        }
+        (self.accept)(backward).then_some(backward)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::{inverse, Tables, MULT_INV};
+    use crate::{
+        crypto::{ipf::ZipCrypto, CryptoProfile},
+        entry::ReadMode,
+        error::IpfError,
+        ipf::{IpfArchive, IpfOptions, IpfWriter},
+    };
+
+    /// Bytes which deflate barely compresses, so the deflated data is long enough to know a part of
+    fn noise(length: usize) -> Vec<u8> {
+        let mut state = 0x2545_F491u32;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    /// Write an entry with a password, then recover the keys knowing `known` decrypted bytes from `offset`
+    fn recover(stride: u32, offset: usize, known: usize) {
+        let profile = CryptoProfile::default()
+            .with_password("not the password of the game")
+            .with_stride(stride);
+        let options = |crypto| IpfOptions {
+            crypto,
+            ..IpfOptions::default()
+        };
+
+        let mut writer = IpfWriter::with_options(Cursor::new(Vec::new()), options(profile.clone()));
+        writer
+            .add_bytes("test.ipf", "data.bin", noise(8192))
+            .unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+
+        let mut plaintext = Vec::new();
+        IpfArchive::with_options(Cursor::new(&archive), options(profile.clone()))
+            .unwrap()
+            .by_index_with(0, ReadMode::Decrypted)
+            .unwrap()
+            .read_to_end(&mut plaintext)
+            .unwrap();
+
+        let unknown = CryptoProfile::default().with_stride(stride);
+        let recovered = IpfArchive::with_options(Cursor::new(&archive), options(unknown))
+            .unwrap()
+            .recover_crypto(0, &plaintext[offset..offset + known], offset)
+            .unwrap();
+        assert_eq!(recovered.keys(), profile.keys());
+    }
+
+    #[test]
+    fn mult_inv_is_inverse() {
+        assert_eq!(MULT_INV.wrapping_mul(0x08088405), 1);
+        assert_eq!(inverse(3).wrapping_mul(3), 1);
+    }
+
+    #[test]
+    fn step_back_undoes_decryption() {
+        let start = ZipCrypto::from_password(b"password").keys();
+        let mut crypto = ZipCrypto::from_keys(start);
+        let cipher = noise(64);
+        let plain: Vec<u8> = cipher
+            .iter()
+            .map(|byte| crypto.decrypt_byte(*byte))
+            .collect();
+
+        let tables = Tables::new();
+        let mut keys = crypto.keys();
+        for (cipher_byte, plain_byte) in cipher.iter().zip(&plain).rev() {
+            assert_eq!(tables.step_back(&mut keys, *cipher_byte), *plain_byte);
+        }
+        assert_eq!(keys, start);
+    }
+
+    fn recover_error(profile: CryptoProfile, plaintext: usize, offset: usize) -> &'static str {
+        match profile.recover_keys(&[0; 100], &vec![0; plaintext], offset) {
+            Err(IpfError::KeyRecovery(message)) => message,
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    #[test]
+    fn recover_keys_checks_plaintext() {
+        let stride_1 = CryptoProfile::default().with_stride(1);
+        let longer = "Plaintext is longer than the entry";
+        let short = "Plaintext has to cover at least 12 encrypted bytes";
+        assert_eq!(recover_error(stride_1.clone(), 101, 0), longer);
+        assert_eq!(recover_error(stride_1.clone(), 20, 81), longer);
+        assert_eq!(recover_error(stride_1.clone(), 1, usize::MAX), longer);
+        assert_eq!(recover_error(stride_1, 11, 0), short);
+        // only every other byte is encrypted
+        assert_eq!(recover_error(CryptoProfile::default(), 22, 0), short);
+        assert_eq!(recover_error(CryptoProfile::default(), 22, 1), short);
+    }
+
+    // each takes about half a minute of CPU time in a release build, run with
+    // cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn recover_stride_1() {
+        recover(1, 0, 2000);
+    }
+
+    #[test]
+    #[ignore]
+    fn recover_stride_2() {
+        recover(2, 0, 4000);
+    }
+
+    #[test]
+    #[ignore]
+    fn recover_stride_2_odd_offset() {
+        recover(2, 1001, 4000);
+    }
+}
@@ -0,0 +1,173 @@
+use std::io::{self, Read};
+
+use flate2::{Decompress, FlushDecompress, Status};
+
+use super::{IpfEntryHeader, Method};
+use crate::crypto::{CryptoProfile, IpfDecryptReader};
+
+/// Bytes from the start of data which are read to detect how it is stored
+const DETECT_LENGTH: u32 = 256;
+
+/// Starts of formats which are not worth to compress
+const MAGIC: [&[u8]; 7] = [
+    b"\xFF\xD8\xFF", // jpg
+    b"\x89PNG",
+    b"FSB5",
+    b"ID3", // mp3
+    b"OggS",
+    b"RIFF", // wav
+    b"DDS ",
+];
+
+impl IpfEntryHeader {
+    /// Set `method` from the data, `reader` is at the start of the data
+    ///
+    /// Reads the first `DETECT_LENGTH` bytes, or all of the data if they look like both
+    /// deflated and encrypted data. Keeps the method from the extension if the data does not tell.
+    pub(crate) fn detect_method(
+        &mut self,
+        reader: impl Read,
+        profile: &CryptoProfile,
+    ) -> io::Result<()> {
+        let mut reader = reader.take(self.compressed_size.into());
+        let mut data = Vec::new();
+        (&mut reader)
+            .take(DETECT_LENGTH.into())
+            .read_to_end(&mut data)?;
+
+        // deflate does not keep the size of data unless it has to store it
+        let same_size = self.compressed_size == self.uncompressed_size;
+        if same_size && data.len() < self.compressed_size as usize {
+            self.method = Method::Stored;
+            return Ok(());
+        }
+
+        let mut encrypted = self.decrypts(&data, profile);
+        let mut deflated = self.inflates(&data);
+        if encrypted && deflated && data.len() < self.compressed_size as usize {
+            reader.read_to_end(&mut data)?;
+            encrypted = self.decrypts(&data, profile);
+            deflated = self.inflates(&data);
+        }
+
+        if encrypted && !deflated {
+            self.method = Method::Encrypted;
+        } else if deflated && !encrypted {
+            self.method = Method::Deflated;
+        } else if !deflated && (same_size || MAGIC.iter().any(|magic| data.starts_with(magic))) {
+            self.method = Method::Stored;
+        }
+        Ok(())
+    }
+
+    /// Whether `input` is the start of encrypted data of this entry
+    fn decrypts(&self, input: &[u8], profile: &CryptoProfile) -> bool {
+        if !profile.is_encrypted() {
+            return false;
+        }
+        let mut decrypted = Vec::with_capacity(input.len());
+        let _ = IpfDecryptReader::new(input, profile).read_to_end(&mut decrypted);
+        self.inflates(&decrypted)
+    }
+
+    /// Whether `input` is the start of deflated data of this entry
+    ///
+    /// If `input` is the whole data, it has to inflate to `uncompressed_size` bytes
+    fn inflates(&self, mut input: &[u8]) -> bool {
+        let whole = input.len() == self.compressed_size as usize;
+        if input.is_empty() {
+            return false;
+        }
+        let mut decompress = Decompress::new(false);
+        let mut output = [0u8; 1024];
+        loop {
+            let total_in = decompress.total_in();
+            let total_out = decompress.total_out();
+            let status = match decompress.decompress(input, &mut output, FlushDecompress::None) {
+                Ok(status) => status,
+                Err(_) => return false,
+            };
+            if decompress.total_out() > self.uncompressed_size.into() {
+                return false;
+            }
+            if status == Status::StreamEnd {
+                return !whole || decompress.total_out() == self.uncompressed_size.into();
+            }
+
+            let consumed = (decompress.total_in() - total_in) as usize;
+            if consumed == 0 && decompress.total_out() == total_out {
+                // out of input before the end of stream
+                return !whole;
+            }
+            input = &input[consumed..];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{
+        crypto::CryptoProfile,
+        entry::Method,
+        ipf::{IpfArchive, IpfOptions, IpfWriter},
+    };
+
+    /// Write `files` with `profile`, then detect their methods with the default profile
+    fn detect(profile: CryptoProfile, files: &[(&str, &[u8])]) -> Vec<Method> {
+        let options = IpfOptions {
+            crypto: profile,
+            ..IpfOptions::default()
+        };
+        let mut writer = IpfWriter::with_options(Cursor::new(Vec::new()), options);
+        for (path, content) in files {
+            writer.add_bytes("test.ipf", *path, content).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let options = IpfOptions {
+            detect_method: true,
+            ..IpfOptions::default()
+        };
+        let archive = IpfArchive::with_options(Cursor::new(bytes), options).unwrap();
+        archive.entries().map(|entry| entry.method()).collect()
+    }
+
+    fn text() -> Vec<u8> {
+        b"<item name=\"sword\" level=\"3\"/>\n".repeat(40)
+    }
+
+    #[test]
+    fn compressed_jpg() {
+        let jpg = [&b"\xFF\xD8\xFF\xE0"[..], &text()].concat();
+        let files = [("a.jpg", &jpg[..])];
+        let encrypted = CryptoProfile::default().with_stored_extensions(Vec::<String>::new());
+        assert_eq!(detect(encrypted, &files), [Method::Encrypted]);
+        let deflated = CryptoProfile::unencrypted().with_stored_extensions(Vec::<String>::new());
+        assert_eq!(detect(deflated, &files), [Method::Deflated]);
+    }
+
+    #[test]
+    fn stored_with_unusual_extension() {
+        let profile = CryptoProfile::default().with_stored_extensions(["dat", "bin", "png"]);
+        let text = text();
+        let png = [&b"\x89PNG"[..], b"short"].concat();
+        let methods = detect(
+            profile,
+            &[
+                ("long.dat", &text),
+                ("short.bin", b"hello"),
+                ("a.png", &png),
+            ],
+        );
+        assert_eq!(methods, [Method::Stored; 3]);
+    }
+
+    #[test]
+    fn empty_entries() {
+        // deflate writes a couple of bytes even for empty data
+        let methods = detect(CryptoProfile::default(), &[("a.jpg", b""), ("a.xml", b"")]);
+        assert_eq!(methods, [Method::Stored, Method::Encrypted]);
+    }
+}
@@ -1,20 +1,25 @@
 use std::{
     borrow::Cow,
-    io::{Cursor, Read, Seek, Take},
+    io::{self, Cursor, Read, Seek, Take},
     path::PathBuf,
 };
 
-use crate::crypto::{IesReader, IpfCrypto};
+use crate::crypto::{CryptoProfile, IpfDecryptReader};
 use crate::error::{IpfError, Result};
+use crate::ies::IesReader;
 
-use flate2::read::DeflateDecoder;
+use flate2::{read::DeflateDecoder, CrcReader};
 
-pub struct IpfEntry<'a> {
-    pub(crate) reader: IpfEntryReader<'a>,
+mod detect;
+
+/// Reader of an entry, `R` reads the data as it is stored in the archive
+pub struct IpfEntry<'a, R: Read = Take<&'a mut dyn Read>> {
+    pub(crate) reader: IpfEntryReader<R>,
     pub(crate) header: Cow<'a, IpfEntryHeader>,
+    pub(crate) verifier: Option<Verifier>,
 }
 
-impl IpfEntry<'_> {
+impl<R: Read> IpfEntry<'_, R> {
     /// Get name of archive.
     /// e.g. example.ipf
     ///
@@ -46,15 +51,206 @@ impl IpfEntry<'_> {
     ///
     /// Sanitize before use
     pub fn full_path(&self) -> PathBuf {
-        let mut f = self.archive_name();
-        f.push(self.path());
-        f
+        self.header.full_path()
     }
+
+    /// Get full path of file which is safe to join to a directory,
+    /// or None if the path tries to get out of it.
+    /// e.g. example.ipf/event_banner/event1234.png
+    ///
+    /// `\` is taken as a separator like `/`. Paths with `..`, absolute paths,
+    /// drive prefixes, NUL bytes and names reserved on Windows (e.g. CON, NUL, COM1) are rejected.
+    pub fn enclosed_path(&self) -> Option<PathBuf> {
+        self.header.enclosed_path()
+    }
+
+    /// How the data is stored, which decides how it is decoded
+    pub fn method(&self) -> Method {
+        self.header.method
+    }
+}
+
+/// Metadata of an entry, which is read from the file table
+/// without touching the data of the entry (unless `IpfOptions::detect_method` is set)
+#[derive(Clone, Copy, Debug)]
+pub struct EntryInfo<'a> {
+    pub(crate) index: usize,
+    pub(crate) header: &'a IpfEntryHeader,
 }
 
-impl Read for IpfEntry<'_> {
+impl EntryInfo<'_> {
+    /// Index of the entry, to be used with `by_index`
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Get name of archive.
+    /// e.g. example.ipf
+    ///
+    /// Sanitize before use
+    pub fn archive_name(&self) -> PathBuf {
+        self.header.archive_name()
+    }
+
+    /// Get file name.
+    /// e.g. event1234.png, map.ies, blah.lua
+    ///
+    /// Sanitize before use
+    pub fn file_name(&self) -> PathBuf {
+        self.header.file_name()
+    }
+
+    /// Get path of entry excluding archive name.
+    /// e.g. event_banner/event1234.png
+    ///
+    /// Sanitize before use
+    pub fn path(&self) -> PathBuf {
+        self.header.path()
+    }
+
+    /// Get full path of file
+    /// e.g. example.ipf/event_banner/event1234.png
+    ///
+    /// Sanitize before use
+    pub fn full_path(&self) -> PathBuf {
+        self.header.full_path()
+    }
+
+    /// Get full path of file which is safe to join to a directory,
+    /// see `IpfEntry::enclosed_path`
+    pub fn enclosed_path(&self) -> Option<PathBuf> {
+        self.header.enclosed_path()
+    }
+
+    /// CRC32 of the data as it is stored in the archive
+    pub fn crc32(&self) -> u32 {
+        self.header.crc32
+    }
+
+    /// Size of the data as it is stored in the archive
+    pub fn compressed_size(&self) -> u32 {
+        self.header.compressed_size
+    }
+
+    /// Size of the data after decompression
+    pub fn uncompressed_size(&self) -> u32 {
+        self.header.uncompressed_size
+    }
+
+    /// Offset of the data from the start of the archive
+    pub fn data_offset(&self) -> u32 {
+        self.header.data_offset
+    }
+
+    /// How the data is stored, from the data itself if `IpfOptions::detect_method` is set,
+    /// otherwise from the extension
+    pub fn method(&self) -> Method {
+        self.header.method
+    }
+
+    /// Whether the data is stored as is, without compression and encryption
+    pub fn is_stored(&self) -> bool {
+        !self.header.is_compressed()
+    }
+
+    /// Whether the data is encrypted (and compressed)
+    pub fn is_encrypted(&self) -> bool {
+        self.header.is_encrypted()
+    }
+}
+
+impl<R: Read> Read for IpfEntry<'_, R> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        self.reader.read(buf)
+        let read = match self.reader.read(buf) {
+            Ok(read) => read,
+            Err(err) => {
+                // corrupted data fails to decompress, report it as a checksum mismatch
+                if let (Some(verifier), Some(raw)) = (&self.verifier, self.reader.raw_mut()) {
+                    verifier.verify_stored(&self.header, raw)?;
+                }
+                return Err(err);
+            }
+        };
+        if let Some(verifier) = &mut self.verifier {
+            verifier.written += read as u64;
+            if read == 0 && !buf.is_empty() {
+                let header = &self.header;
+                match &mut self.reader {
+                    // raw data of compressed entry
+                    IpfEntryReader::Stored(r) if header.is_compressed() => {
+                        verifier.verify_stored(header, r)?
+                    }
+                    IpfEntryReader::Stored(r) => verifier.verify(header, r, verifier.written)?,
+                    IpfEntryReader::Decrypted(r) => verifier.verify_stored(header, r.get_mut())?,
+                    IpfEntryReader::Ipf(r) => {
+                        verifier.verify(header, r.get_mut().get_mut(), verifier.written)?
+                    }
+                    // verified when the entry was opened
+                    IpfEntryReader::Ies(_) => {}
+                }
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// Checks crc32 and size of an entry when its data is fully read
+pub(crate) struct Verifier {
+    pub(crate) index: usize,
+    pub(crate) written: u64,
+}
+
+impl Verifier {
+    pub(crate) fn new(index: usize) -> Self {
+        Self { index, written: 0 }
+    }
+
+    /// `uncompressed` is the number of bytes after decompression
+    pub(crate) fn verify<R: Read>(
+        &self,
+        header: &IpfEntryHeader,
+        raw: &mut RawReader<R>,
+        uncompressed: u64,
+    ) -> Result<()> {
+        self.verify_stored(header, raw)?;
+        if uncompressed != header.uncompressed_size.into() {
+            return Err(IpfError::SizeMismatch {
+                index: self.index,
+                name: header.full_path().to_string_lossy().into_owned(),
+                expected: header.uncompressed_size.into(),
+                actual: uncompressed,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check only the data as it is stored in the archive
+    pub(crate) fn verify_stored<R: Read>(
+        &self,
+        header: &IpfEntryHeader,
+        raw: &mut RawReader<R>,
+    ) -> Result<()> {
+        // deflate stream may end before the stored data does
+        io::copy(raw, &mut io::sink())?;
+
+        let name = || header.full_path().to_string_lossy().into_owned();
+        if raw.crc().sum() != header.crc32 {
+            return Err(IpfError::ChecksumMismatch {
+                index: self.index,
+                name: name(),
+                expected: header.crc32,
+                actual: raw.crc().sum(),
+            });
+        }
+        if u64::from(raw.crc().amount()) != header.compressed_size.into() {
+            return Err(IpfError::SizeMismatch {
+                index: self.index,
+                name: name(),
+                expected: header.compressed_size.into(),
+                actual: raw.crc().amount().into(),
+            });
+        }
+        Ok(())
     }
 }
 
@@ -62,16 +258,27 @@ impl Read for IpfEntry<'_> {
 pub(crate) struct IpfEntryHeader {
     pub(crate) file_name: String,
     pub(crate) archive_name: String,
-    #[allow(dead_code)]
     pub(crate) crc32: u32,
     pub(crate) compressed_size: u32,
-    #[allow(dead_code)]
     pub(crate) uncompressed_size: u32,
     pub(crate) data_offset: u32,
+    /// not in the file table, decided by `CryptoProfile` or detected from the data
+    pub(crate) method: Method,
+}
+
+/// How data of an entry is stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    /// As is, without compression and encryption
+    Stored,
+    /// Deflated
+    Deflated,
+    /// Deflated and then encrypted
+    Encrypted,
 }
 
 impl IpfEntryHeader {
-    pub(crate) fn parse(mut reader: (impl Read + Seek)) -> Result<Self> {
+    pub(crate) fn parse(mut reader: impl Read + Seek, profile: &CryptoProfile) -> Result<Self> {
         let mut buffer = [0u8; 20];
         reader.read_exact(&mut buffer)?;
 
@@ -99,6 +306,7 @@ impl IpfEntryHeader {
         };
 
         Ok(Self {
+            method: profile.method(&file_name),
             file_name,
             archive_name,
             crc32,
@@ -108,6 +316,11 @@ impl IpfEntryHeader {
         })
     }
 
+    pub(crate) fn is_ies(&self) -> bool {
+        self.extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("ies"))
+    }
+
     pub(crate) fn extension(&self) -> Option<String> {
         let extension = std::path::PathBuf::from(&self.file_name);
         extension
@@ -120,41 +333,81 @@ impl IpfEntryHeader {
     }
 
     pub(crate) fn file_name(&self) -> PathBuf {
-        PathBuf::from(self.file_name.split('/').last().unwrap())
+        PathBuf::from(self.file_name.split('/').next_back().unwrap())
     }
 
     pub(crate) fn path(&self) -> PathBuf {
         PathBuf::from(&self.file_name)
     }
 
-    fn into_bytes(self) -> Vec<u8> {
+    pub(crate) fn full_path(&self) -> PathBuf {
+        let mut f = self.archive_name();
+        f.push(self.path());
+        f
+    }
+
+    /// full path which is safe to join to a directory, see `IpfEntry::enclosed_path`
+    pub(crate) fn enclosed_path(&self) -> Option<PathBuf> {
+        const RESERVED: [&str; 22] = [
+            "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7",
+            "com8", "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+        ];
+
+        let mut path = PathBuf::new();
+        for name in [&self.archive_name, &self.file_name] {
+            if name.starts_with(['/', '\\']) {
+                return None;
+            }
+            for part in name.split(['/', '\\']) {
+                if part.is_empty() || part == "." {
+                    continue;
+                }
+                // drive prefixes and alternate data streams contain ':'
+                if part == ".." || part.contains(['\0', ':']) {
+                    return None;
+                }
+                let stem = part.split('.').next().unwrap().trim_end();
+                if RESERVED.contains(&stem.to_ascii_lowercase().as_str()) {
+                    return None;
+                }
+                path.push(part);
+            }
+        }
+        (!path.as_os_str().is_empty()).then_some(path)
+    }
+
+    /// full path to compare entries of different archives,
+    /// case-insensitive with `/` and `\` as separators like on Windows
+    pub(crate) fn key(&self) -> String {
+        normalize_path(&format!("{}/{}", self.archive_name, self.file_name))
+    }
+
+    /// caller must make sure both names fit in u16 length
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
         let mut array = Vec::new();
-        array.append(&mut self.file_name.len().to_le_bytes().into());
+        array.append(&mut (self.file_name.len() as u16).to_le_bytes().into());
         array.append(&mut self.crc32.to_le_bytes().into());
         array.append(&mut self.compressed_size.to_le_bytes().into());
         array.append(&mut self.uncompressed_size.to_le_bytes().into());
         array.append(&mut self.data_offset.to_le_bytes().into());
-        array.append(&mut self.archive_name.len().to_le_bytes().into());
+        array.append(&mut (self.archive_name.len() as u16).to_le_bytes().into());
         array.append(&mut self.archive_name.as_bytes().into());
         array.append(&mut self.file_name.as_bytes().into());
         array
     }
 
-    /// do not compress and crypt these extensions
-    pub(crate) fn worth_compress(&self) -> bool {
-        const NOT_WORTH: [&str; 3] = ["jpg", "fsb", "mp3"];
-        let extension = std::path::PathBuf::from(&self.file_name);
-        let extension = match extension.extension() {
-            Some(ext) => ext,
-            None => return true,
-        };
+    /// whether data is deflated (and maybe encrypted)
+    pub(crate) fn is_compressed(&self) -> bool {
+        self.method != Method::Stored
+    }
 
-        for not_worth in NOT_WORTH {
-            if extension.to_ascii_lowercase() == not_worth {
-                return false;
-            }
-        }
-        true
+    pub(crate) fn is_encrypted(&self) -> bool {
+        self.method == Method::Encrypted
+    }
+
+    /// `profile` to decrypt the data with, None if not encrypted
+    pub(crate) fn crypto<'a>(&self, profile: &'a CryptoProfile) -> Option<&'a CryptoProfile> {
+        self.is_encrypted().then_some(profile)
     }
 }
 
@@ -164,18 +417,132 @@ impl From<IpfEntryHeader> for Vec<u8> {
     }
 }
 
-pub(crate) enum IpfEntryReader<'a> {
-    Stored(Take<&'a mut dyn Read>),
-    Ipf(DeflateDecoder<IpfCrypto<Take<&'a mut dyn Read>>>),
+/// see `IpfEntryHeader::key`
+pub(crate) fn normalize_path(path: &str) -> String {
+    path.replace('\\', "/")
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+        .to_ascii_lowercase()
+}
+
+/// Data of an entry as it is stored in the archive
+pub(crate) type RawReader<R> = CrcReader<R>;
+
+/// Which stage of decoding an entry is read at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadMode {
+    /// Data as it is stored in the archive, still encrypted and compressed
+    Raw,
+    /// Decrypted but still compressed (deflate) data
+    Decrypted,
+    /// Decompressed data, e.g. the original binary of ies files
+    Decompressed,
+    /// Decompressed data converted to a readable format, e.g. ies files to csv
+    Converted,
+}
+
+pub(crate) enum IpfEntryReader<R: Read> {
+    Stored(RawReader<R>),
+    Decrypted(IpfDecryptReader<RawReader<R>>),
+    Ipf(DeflateDecoder<IpfDecryptReader<RawReader<R>>>),
     Ies(IesReader<Cursor<Vec<u8>>>),
 }
 
-impl Read for IpfEntryReader<'_> {
+impl<R: Read> IpfEntryReader<R> {
+    /// data as it is stored in the archive, None if it is already read as a whole
+    fn raw_mut(&mut self) -> Option<&mut RawReader<R>> {
+        match self {
+            IpfEntryReader::Stored(r) => Some(r),
+            IpfEntryReader::Decrypted(r) => Some(r.get_mut()),
+            IpfEntryReader::Ipf(r) => Some(r.get_mut().get_mut()),
+            IpfEntryReader::Ies(_) => None,
+        }
+    }
+}
+
+impl<R: Read> Read for IpfEntryReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         match self {
             IpfEntryReader::Stored(r) => r.read(buf),
+            IpfEntryReader::Decrypted(r) => r.read(buf),
             IpfEntryReader::Ipf(r) => r.read(buf),
             IpfEntryReader::Ies(r) => r.read(buf),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{IpfEntryHeader, Method};
+
+    fn enclosed(archive_name: &str, file_name: &str) -> Option<PathBuf> {
+        IpfEntryHeader {
+            file_name: file_name.to_string(),
+            archive_name: archive_name.to_string(),
+            crc32: 0,
+            compressed_size: 0,
+            uncompressed_size: 0,
+            data_offset: 0,
+            method: Method::Stored,
+        }
+        .enclosed_path()
+    }
+
+    #[test]
+    fn nested_path() {
+        assert_eq!(
+            enclosed("ui.ipf", "event_banner/event1234.png"),
+            Some(PathBuf::from("ui.ipf/event_banner/event1234.png"))
+        );
+        assert_eq!(
+            enclosed("ui.ipf", "event_banner\\./event1234.png"),
+            Some(PathBuf::from("ui.ipf/event_banner/event1234.png"))
+        );
+    }
+
+    #[test]
+    fn parent_directory() {
+        assert_eq!(enclosed("ui.ipf", "../x"), None);
+        assert_eq!(enclosed("ui.ipf", "..\\..\\x"), None);
+        assert_eq!(enclosed("ui.ipf", "a/../../x"), None);
+        assert_eq!(enclosed("..", "x"), None);
+    }
+
+    #[test]
+    fn absolute_path() {
+        assert_eq!(enclosed("ui.ipf", "/etc/passwd"), None);
+        assert_eq!(enclosed("ui.ipf", "\\Windows\\x"), None);
+        assert_eq!(enclosed("/tmp", "x"), None);
+    }
+
+    #[test]
+    fn drive_prefix_and_stream() {
+        assert_eq!(enclosed("ui.ipf", "C:"), None);
+        assert_eq!(enclosed("ui.ipf", "C:\\x"), None);
+        assert_eq!(enclosed("ui.ipf", "x:y"), None);
+    }
+
+    #[test]
+    fn nul_byte() {
+        assert_eq!(enclosed("ui.ipf", "a\0b"), None);
+    }
+
+    #[test]
+    fn reserved_names() {
+        assert_eq!(enclosed("ui.ipf", "con.txt"), None);
+        assert_eq!(enclosed("ui.ipf", "dir/COM1"), None);
+        assert_eq!(enclosed("ui.ipf", "nul "), None);
+        assert_eq!(enclosed("Aux.ipf", "x"), None);
+        assert!(enclosed("ui.ipf", "console.txt").is_some());
+    }
+
+    #[test]
+    fn empty_path() {
+        assert_eq!(enclosed("", ""), None);
+        assert_eq!(enclosed(".", "./"), None);
+    }
+}
@@ -16,6 +16,51 @@ pub enum IpfError {
     #[error("Specified file not found in archive")]
     FileNotFound,
 
+    /// The data of an entry does not match the crc32 in the file table
+    #[error("Checksum mismatch in entry {index} ({name}): expected {expected:#010x}, got {actual:#010x}")]
+    ChecksumMismatch {
+        index: usize,
+        name: String,
+        expected: u32,
+        actual: u32,
+    },
+
+    /// The data of an entry does not match the size in the file table
+    #[error("Size mismatch in entry {index} ({name}): expected {expected} bytes, got {actual}")]
+    SizeMismatch {
+        index: usize,
+        name: String,
+        expected: u64,
+        actual: u64,
+    },
+
+    /// The path of an entry would get out of the directory it is extracted to
+    #[error("Unsafe path in entry {index}: {name:?}")]
+    UnsafePath { index: usize, name: String },
+
+    /// Keys of ZipCrypto could not be recovered from the known plaintext
+    #[error("Key recovery failed: {0}")]
+    KeyRecovery(&'static str),
+
+    /// The csv file could not be read as an ies table
+    ///
+    /// `line` and `column` start from 1, `column` is 0 if the error is not about a specific column
+    /// and both are 0 if `CsvOptions` are invalid
+    #[error("Invalid csv at line {line}, column {column}: {message}")]
+    Csv {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+
+    /// A row of ies table could not be deserialized, only returned with the `serde` feature
+    #[error("Failed to deserialize row {row}, column {}: {message}", .column.as_deref().unwrap_or("-"))]
+    Deserialize {
+        row: usize,
+        column: Option<String>,
+        message: String,
+    },
+
     /// Decoding a UTF-8 string failed
     #[error("Invalid UTF-8")]
     Encoding(#[from] std::string::FromUtf8Error),
@@ -23,6 +68,6 @@ pub enum IpfError {
 
 impl From<IpfError> for std::io::Error {
     fn from(err: IpfError) -> std::io::Error {
-        std::io::Error::new(std::io::ErrorKind::Other, err)
+        std::io::Error::other(err)
     }
 }
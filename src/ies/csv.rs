@@ -0,0 +1,702 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    sync::Arc,
+};
+
+use super::{column_names, IesCell, IesColumn, IesHeader, IesRow, IesTable};
+use crate::error::{IpfError, Result};
+
+/// Column of csv which holds class id of rows, unless the table has a column of the same name
+const CLASS_ID: &str = "ClassID";
+/// Column of csv which holds class name of rows, unless the table has a column of the same name
+const CLASS_NAME: &str = "ClassName";
+/// Column of csv which holds unknown bytes of rows as hex, e.g. 0100
+const STRING_FLAGS: &str = "StringFlags";
+
+/// Format of csv (or tsv) files
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    /// Separator of fields, `b','` for csv and `b'\t'` for tsv
+    ///
+    /// Has to be an ASCII character other than `"`, `\r` and `\n`
+    pub delimiter: u8,
+    /// Whether to write `ClassID` and `ClassName` of rows before the columns,
+    /// except those the table already has as columns. On by default
+    pub class_columns: bool,
+    /// Whether to write `StringFlags` (see `IesRow::string_flags`) of rows as hex after the columns.
+    /// On by default
+    pub string_flags: bool,
+    /// How to write numbers
+    pub numbers: NumberFormat,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            class_columns: true,
+            string_flags: true,
+            numbers: NumberFormat::Integer,
+        }
+    }
+}
+
+impl CsvOptions {
+    fn check(&self) -> Result<()> {
+        if !self.delimiter.is_ascii() || matches!(self.delimiter, b'"' | b'\r' | b'\n') {
+            return Err(csv_error(
+                0,
+                0,
+                format!("invalid delimiter {:?}", char::from(self.delimiter)),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Comma separated values
+    pub fn csv() -> Self {
+        Self::default()
+    }
+
+    /// Tab separated values
+    pub fn tsv() -> Self {
+        Self {
+            delimiter: b'\t',
+            ..Self::default()
+        }
+    }
+}
+
+/// How to write numbers of ies table, which are all stored as f32
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumberFormat {
+    /// Whole numbers as integers, e.g. 1 and 2.5
+    Integer,
+    /// Every number with a decimal point, e.g. 1.0 and 2.5
+    Float,
+}
+
+impl NumberFormat {
+    fn format(self, number: f32) -> String {
+        match self {
+            NumberFormat::Integer => number.to_string(),
+            NumberFormat::Float => format!("{number:?}"),
+        }
+    }
+}
+
+impl IesTable {
+    /// Write the table as csv (RFC 4180), with a header line of column names
+    pub fn write_csv(&self, mut writer: impl Write, options: &CsvOptions) -> Result<()> {
+        options.check()?;
+        let names = column_names(&self.columns);
+        let class_id = options.class_columns && !names.contains_key(CLASS_ID);
+        let class_name = options.class_columns && !names.contains_key(CLASS_NAME);
+
+        let mut line = String::new();
+        let mut fields: Vec<&str> = Vec::with_capacity(self.columns.len() + 2);
+        if class_id {
+            fields.push(CLASS_ID);
+        }
+        if class_name {
+            fields.push(CLASS_NAME);
+        }
+        fields.extend(self.columns.iter().map(|column| column.name1.as_str()));
+        if options.string_flags {
+            fields.push(STRING_FLAGS);
+        }
+        write_record(&mut writer, &mut line, &fields, options.delimiter)?;
+
+        for row in &self.rows {
+            let mut fields = Vec::with_capacity(fields.len());
+            if class_id {
+                fields.push(row.class_id.to_string());
+            }
+            if class_name {
+                fields.push(row.class_name.clone());
+            }
+            fields.extend(row.cells.iter().map(|cell| match cell {
+                IesCell::Int(number) => options.numbers.format(*number),
+                IesCell::Str(string) => string.clone(),
+            }));
+            if options.string_flags {
+                fields.push(
+                    row.string_flags
+                        .iter()
+                        .map(|f| format!("{f:02x}"))
+                        .collect(),
+                );
+            }
+            write_record(&mut writer, &mut line, &fields, options.delimiter)?;
+        }
+        Ok(())
+    }
+
+    /// Read a csv file as a new table named `name`, which has `columns`
+    ///
+    /// The first line of the csv must be names of the columns, in any order.
+    /// Like `parse`, number columns come first and then string columns,
+    /// each in the order they are given in `columns`.
+    ///
+    /// Class id and class name of rows are read from `ClassID` and `ClassName` fields
+    /// (columns of the table or extra fields of the csv). Without them, class id is
+    /// the row number starting from 1 and class name is empty.
+    /// Likewise, `StringFlags` field is read if any, otherwise they are all 0.
+    pub fn from_csv(
+        reader: impl Read,
+        name: impl Into<String>,
+        columns: Vec<IesColumn>,
+        options: &CsvOptions,
+    ) -> Result<Self> {
+        let (mut columns, str_columns): (Vec<_>, Vec<_>) =
+            columns.into_iter().partition(IesColumn::is_number);
+        for (order, column) in columns.iter_mut().enumerate() {
+            column.order = order as u16;
+        }
+        for (order, mut column) in str_columns.into_iter().enumerate() {
+            column.order = order as u16;
+            columns.push(column);
+        }
+        let file_order = (0..columns.len()).collect();
+
+        read_csv(
+            reader,
+            IesHeader::new(name.into()),
+            columns,
+            file_order,
+            &[],
+            options,
+        )
+    }
+
+    /// Read a csv file as a new table, which has the same name and columns as `template`
+    ///
+    /// Class name and unknown fields of rows, which are not in the csv,
+    /// are copied from the row of the same class id in `template` if any.
+    /// See `from_csv` for the format of csv.
+    pub fn from_csv_with_template(
+        reader: impl Read,
+        template: &IesTable,
+        options: &CsvOptions,
+    ) -> Result<Self> {
+        let mut header = IesHeader::new(template.header.name.clone());
+        header.unknown1 = template.header.unknown1;
+        header.unknown2 = template.header.unknown2;
+        header.padding = template.header.padding.clone();
+
+        read_csv(
+            reader,
+            header,
+            template.columns.clone(),
+            template.file_order.clone(),
+            &template.rows,
+            options,
+        )
+    }
+}
+
+enum Field {
+    Column(usize),
+    ClassId,
+    ClassName,
+    StringFlags,
+}
+
+fn read_csv(
+    mut reader: impl Read,
+    header: IesHeader,
+    columns: Vec<IesColumn>,
+    file_order: Vec<usize>,
+    template_rows: &[IesRow],
+    options: &CsvOptions,
+) -> Result<IesTable> {
+    options.check()?;
+    let mut input = Vec::new();
+    reader.read_to_end(&mut input)?;
+    let input = String::from_utf8(input)?;
+    let mut records = Records::new(&input, options.delimiter);
+
+    let names = Arc::new(column_names(&columns));
+    let (_, header_fields) = match records.next() {
+        Some(record) => record?,
+        None => return Err(csv_error(1, 0, "missing header line")),
+    };
+
+    let mut fields = Vec::with_capacity(header_fields.len());
+    for (index, name) in header_fields.iter().enumerate() {
+        let field = match names.get(name.as_str()) {
+            Some(column) => Field::Column(*column),
+            None if name == CLASS_ID => Field::ClassId,
+            None if name == CLASS_NAME => Field::ClassName,
+            None if name == STRING_FLAGS => Field::StringFlags,
+            None => return Err(csv_error(1, index + 1, format!("unknown column {name}"))),
+        };
+        if header_fields[..index].contains(name) {
+            return Err(csv_error(1, index + 1, format!("duplicate column {name}")));
+        }
+        fields.push(field);
+    }
+    if let Some(missing) = columns
+        .iter()
+        .find(|column| !header_fields.contains(&column.name1))
+    {
+        return Err(csv_error(1, 0, format!("missing column {}", missing.name1)));
+    }
+
+    let template_rows: HashMap<u32, &IesRow> = template_rows
+        .iter()
+        .map(|row| (row.class_id, row))
+        .collect();
+    let str_column_count = columns.iter().filter(|c| c.is_string()).count();
+
+    let mut rows = Vec::new();
+    for record in records {
+        let (line, values) = record?;
+        if values.len() != fields.len() {
+            return Err(csv_error(
+                line,
+                values.len().min(fields.len()) + 1,
+                format!("expected {} fields, found {}", fields.len(), values.len()),
+            ));
+        }
+
+        let mut cells = vec![IesCell::Int(0.0); columns.len()];
+        let mut class_id = None;
+        let mut class_name = None;
+        let mut string_flags = None;
+        for (index, (field, value)) in fields.iter().zip(values).enumerate() {
+            match field {
+                Field::Column(column) if columns[*column].is_string() => {
+                    cells[*column] = IesCell::Str(value)
+                }
+                Field::Column(column) => match value.trim().parse::<f32>() {
+                    Ok(number) => cells[*column] = IesCell::Int(number),
+                    Err(_) => {
+                        return Err(csv_error(
+                            line,
+                            index + 1,
+                            format!("invalid number {value:?}"),
+                        ))
+                    }
+                },
+                Field::ClassId => match value.trim().parse::<u32>() {
+                    Ok(id) => class_id = Some(id),
+                    Err(_) => {
+                        return Err(csv_error(
+                            line,
+                            index + 1,
+                            format!("invalid class id {value:?}"),
+                        ))
+                    }
+                },
+                Field::ClassName => class_name = Some(value),
+                Field::StringFlags => match parse_hex(&value) {
+                    Some(flags) if flags.len() == str_column_count => string_flags = Some(flags),
+                    _ => {
+                        return Err(csv_error(
+                            line,
+                            index + 1,
+                            format!("expected {str_column_count} hex bytes, found {value:?}"),
+                        ))
+                    }
+                },
+            }
+        }
+
+        let row_number = rows.len() as u32 + 1;
+        let class_id = match (class_id, names.get(CLASS_ID)) {
+            (Some(id), _) => id,
+            (None, Some(column)) => match cells[*column] {
+                IesCell::Int(id) if id >= 0.0 && id.fract() == 0.0 && id <= u32::MAX as f32 => {
+                    id as u32
+                }
+                IesCell::Int(id) => {
+                    let index = fields
+                        .iter()
+                        .position(|field| matches!(field, Field::Column(c) if c == column))
+                        .unwrap();
+                    return Err(csv_error(line, index + 1, format!("invalid class id {id}")));
+                }
+                IesCell::Str(_) => row_number,
+            },
+            (None, None) => row_number,
+        };
+        let template = template_rows.get(&class_id);
+        let class_name = match (class_name, names.get(CLASS_NAME), template) {
+            (Some(name), _, _) => name,
+            (None, Some(column), _) => cells[*column].as_str().unwrap_or_default().to_string(),
+            (None, None, Some(row)) => row.class_name.clone(),
+            (None, None, None) => String::new(),
+        };
+
+        let string_flags = match (string_flags, template) {
+            (Some(flags), _) => flags,
+            (None, Some(row)) if row.string_flags.len() == str_column_count => {
+                row.string_flags.clone()
+            }
+            _ => vec![0; str_column_count],
+        };
+        rows.push(IesRow {
+            class_id,
+            class_name,
+            cells,
+            string_flags,
+            names: Arc::clone(&names),
+        });
+    }
+
+    Ok(IesTable {
+        header,
+        columns,
+        rows,
+        file_order,
+    })
+}
+
+fn parse_hex(value: &str) -> Option<Vec<u8>> {
+    let value = value.trim();
+    if !value.is_ascii() || !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&value[index..index + 2], 16).ok())
+        .collect()
+}
+
+fn write_record(
+    mut writer: impl Write,
+    line: &mut String,
+    fields: &[impl AsRef<str>],
+    delimiter: u8,
+) -> Result<()> {
+    line.clear();
+    for (index, field) in fields.iter().enumerate() {
+        if index != 0 {
+            line.push(char::from(delimiter));
+        }
+        let field = field.as_ref();
+        // a record of one empty field would be a blank line, which readers skip
+        if field.contains(|c| c == '"' || c == '\r' || c == '\n' || c == char::from(delimiter))
+            || (field.is_empty() && fields.len() == 1)
+        {
+            line.push('"');
+            line.push_str(&field.replace('"', "\"\""));
+            line.push('"');
+        } else {
+            line.push_str(field);
+        }
+    }
+    line.push_str("\r\n");
+    writer.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+fn csv_error(line: usize, column: usize, message: impl Into<String>) -> IpfError {
+    IpfError::Csv {
+        line,
+        column,
+        message: message.into(),
+    }
+}
+
+/// Records of RFC 4180 csv, with the line number each record starts at
+struct Records<'a> {
+    input: &'a str,
+    position: usize,
+    line: usize,
+    delimiter: u8,
+}
+
+impl<'a> Records<'a> {
+    fn new(input: &'a str, delimiter: u8) -> Self {
+        Self {
+            // byte order mark written by some spreadsheets
+            input: input.strip_prefix('\u{feff}').unwrap_or(input),
+            position: 0,
+            line: 1,
+            delimiter,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.position).copied()
+    }
+
+    /// consume a line break if there is one
+    fn line_break(&mut self) -> bool {
+        let rest = &self.input.as_bytes()[self.position..];
+        let length = if rest.starts_with(b"\r\n") {
+            2
+        } else if rest.starts_with(b"\n") {
+            1
+        } else {
+            return false;
+        };
+        self.position += length;
+        self.line += 1;
+        true
+    }
+
+    fn record(&mut self) -> Result<Vec<String>> {
+        let start_line = self.line;
+        let mut fields = Vec::new();
+        loop {
+            let field = if self.peek() == Some(b'"') {
+                self.quoted(start_line, fields.len() + 1)?
+            } else {
+                let rest = &self.input[self.position..];
+                let end = rest
+                    .bytes()
+                    .position(|byte| byte == self.delimiter || byte == b'\n')
+                    .unwrap_or(rest.len());
+                self.position += end;
+                rest[..end]
+                    .strip_suffix('\r')
+                    .unwrap_or(&rest[..end])
+                    .to_string()
+            };
+            fields.push(field);
+
+            match self.peek() {
+                Some(byte) if byte == self.delimiter => self.position += 1,
+                None => return Ok(fields),
+                Some(_) if self.line_break() => return Ok(fields),
+                Some(_) => {
+                    return Err(csv_error(
+                        self.line,
+                        fields.len(),
+                        "unexpected character after closing quote",
+                    ))
+                }
+            }
+        }
+    }
+
+    fn quoted(&mut self, start_line: usize, column: usize) -> Result<String> {
+        // skip opening quote
+        self.position += 1;
+        let mut field = String::new();
+        loop {
+            let rest = &self.input[self.position..];
+            let end = match rest.find('"') {
+                Some(end) => end,
+                None => return Err(csv_error(start_line, column, "unterminated quote")),
+            };
+            field.push_str(&rest[..end]);
+            self.line += rest[..end].matches('\n').count();
+            self.position += end + 1;
+
+            // escaped quote
+            if self.peek() == Some(b'"') {
+                field.push('"');
+                self.position += 1;
+            } else {
+                return Ok(field);
+            }
+        }
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = Result<(usize, Vec<String>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // skip empty lines
+        while self.line_break() {}
+        if self.position >= self.input.len() {
+            return None;
+        }
+        let line = self.line;
+        Some(self.record().map(|fields| (line, fields)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CsvOptions, NumberFormat};
+    use crate::{
+        error::IpfError,
+        ies::{IesCell, IesColumn, IesTable},
+    };
+
+    fn table(columns: Vec<IesColumn>, csv: &str) -> IesTable {
+        IesTable::from_csv(csv.as_bytes(), "Test", columns, &CsvOptions::default()).unwrap()
+    }
+
+    fn write(table: &IesTable, options: &CsvOptions) -> String {
+        let mut csv = Vec::new();
+        table.write_csv(&mut csv, options).unwrap();
+        String::from_utf8(csv).unwrap()
+    }
+
+    fn bare() -> CsvOptions {
+        CsvOptions {
+            class_columns: false,
+            string_flags: false,
+            ..CsvOptions::default()
+        }
+    }
+
+    #[test]
+    fn quotes_fields_which_need_it() {
+        let mut table = table(vec![IesColumn::new("Name", true)], "Name\r\n");
+        for name in ["plain", "a,b", "say \"hi\"", "two\nlines", "cr\r"] {
+            table
+                .push_row(1, "", vec![IesCell::Str(name.to_string())])
+                .unwrap();
+        }
+        assert_eq!(
+            write(&table, &bare()),
+            "Name\r\nplain\r\n\"a,b\"\r\n\"say \"\"hi\"\"\"\r\n\"two\nlines\"\r\n\"cr\r\"\r\n"
+        );
+    }
+
+    #[test]
+    fn empty_sole_field_is_kept() {
+        let columns = vec![IesColumn::new("Name", true)];
+        let table = table(columns.clone(), "Name\r\nx\r\n\"\"\r\ny\r\n");
+        assert_eq!(table.len(), 3);
+
+        let csv = write(&table, &bare());
+        assert_eq!(csv, "Name\r\nx\r\n\"\"\r\ny\r\n");
+        let read = IesTable::from_csv(csv.as_bytes(), "Test", columns, &bare()).unwrap();
+        assert_eq!(read.len(), 3);
+        assert_eq!(read.rows()[1].get_str("Name"), Some(""));
+    }
+
+    #[test]
+    fn number_format() {
+        let table = table(vec![IesColumn::new("Value", false)], "Value\n1\n2.5\n-3\n");
+        assert_eq!(write(&table, &bare()), "Value\r\n1\r\n2.5\r\n-3\r\n");
+        let float = CsvOptions {
+            numbers: NumberFormat::Float,
+            ..bare()
+        };
+        assert_eq!(write(&table, &float), "Value\r\n1.0\r\n2.5\r\n-3.0\r\n");
+    }
+
+    #[test]
+    fn tsv_with_class_columns_and_string_flags() {
+        let columns = vec![IesColumn::new("Level", false), IesColumn::new("Name", true)];
+        let table = table(
+            columns,
+            "ClassID,ClassName,Level,Name,StringFlags\n5,Sword,3,\"a\tb\",01\n",
+        );
+        assert_eq!(
+            write(&table, &CsvOptions::tsv()),
+            "ClassID\tClassName\tLevel\tName\tStringFlags\r\n5\tSword\t3\t\"a\tb\"\t01\r\n"
+        );
+    }
+
+    fn columns() -> Vec<IesColumn> {
+        vec![IesColumn::new("Level", false), IesColumn::new("Name", true)]
+    }
+
+    /// line, column and message of the error of reading `csv`
+    fn error(csv: &str) -> (usize, usize, String) {
+        match IesTable::from_csv(csv.as_bytes(), "Test", columns(), &CsvOptions::default()) {
+            Err(IpfError::Csv {
+                line,
+                column,
+                message,
+            }) => (line, column, message),
+            Err(err) => panic!("unexpected error {err}"),
+            Ok(_) => panic!("no error"),
+        }
+    }
+
+    #[test]
+    fn header_errors() {
+        assert_eq!(error(""), (1, 0, "missing header line".into()));
+        assert_eq!(
+            error("Level,Name,Size\n"),
+            (1, 3, "unknown column Size".into())
+        );
+        assert_eq!(
+            error("Level,Name,Level\n"),
+            (1, 3, "duplicate column Level".into())
+        );
+        assert_eq!(error("Name\n"), (1, 0, "missing column Level".into()));
+    }
+
+    #[test]
+    fn field_errors() {
+        assert_eq!(
+            error("Level,Name\n1,a\nhigh,b\n"),
+            (3, 1, "invalid number \"high\"".into())
+        );
+        assert_eq!(
+            error("ClassID,Level,Name\n1,1,a\n-2,1,b\n"),
+            (3, 1, "invalid class id \"-2\"".into())
+        );
+        assert_eq!(
+            error("Level,Name\n1,a,b\n"),
+            (2, 3, "expected 2 fields, found 3".into())
+        );
+        assert_eq!(
+            error("Level,Name,StringFlags\n1,a,0102\n"),
+            (2, 3, "expected 1 hex bytes, found \"0102\"".into())
+        );
+        assert_eq!(
+            error("Level,Name\n1,\"a\n"),
+            (2, 2, "unterminated quote".into())
+        );
+    }
+
+    #[test]
+    fn lines_are_counted_in_quoted_fields() {
+        let (line, column, _) = error("Level,Name\r\n1,\"two\r\nlines\"\r\n\r\nx,b\r\n");
+        assert_eq!((line, column), (5, 1));
+
+        let table = table(columns(), "Level,Name\n1,\"two\nlines\"\n");
+        assert_eq!(table.rows()[0].get_str("Name"), Some("two\nlines"));
+        assert_eq!(table.rows()[0].class_id(), 1);
+    }
+
+    #[test]
+    fn template_fills_missing_fields() {
+        let template = table(
+            columns(),
+            "ClassID,ClassName,Level,Name,StringFlags\n7,Sword,1,a,01\n8,Shield,2,b,02\n",
+        );
+        let csv = "ClassID,Level,Name\n8,5,c\n9,6,d\n";
+        let table =
+            IesTable::from_csv_with_template(csv.as_bytes(), &template, &CsvOptions::default())
+                .unwrap();
+        assert_eq!(table.name(), "Test");
+
+        let rows = table.rows();
+        assert_eq!((rows[0].class_id(), rows[0].class_name()), (8, "Shield"));
+        assert_eq!(rows[0].string_flags(), [2]);
+        assert_eq!(rows[0].get_f32("Level"), Some(5.0));
+        assert_eq!((rows[1].class_id(), rows[1].class_name()), (9, ""));
+        assert_eq!(rows[1].string_flags(), [0]);
+    }
+
+    #[test]
+    fn invalid_delimiter() {
+        for delimiter in [0xA7, b'"', b'\n'] {
+            let options = CsvOptions {
+                delimiter,
+                ..CsvOptions::default()
+            };
+            let err =
+                IesTable::from_csv("Level\u{a7}Name\n".as_bytes(), "Test", columns(), &options)
+                    .err()
+                    .unwrap();
+            assert!(
+                matches!(
+                    err,
+                    IpfError::Csv {
+                        line: 0,
+                        column: 0,
+                        ..
+                    }
+                ),
+                "{err}"
+            );
+        }
+    }
+}
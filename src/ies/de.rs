@@ -0,0 +1,407 @@
+use std::{fmt, str::FromStr};
+
+use serde::de::{
+    self, value::BorrowedStrDeserializer, DeserializeSeed, Deserializer, IntoDeserializer,
+    MapAccess, Unexpected, Visitor,
+};
+use serde::Deserialize;
+
+use super::{IesCell, IesRow, IesTable};
+use crate::error::{IpfError, Result};
+
+impl IesTable {
+    /// Deserialize every row into `T`, e.g. a struct with fields named after the columns
+    ///
+    /// Besides the columns, `ClassID` and `ClassName` of rows can be deserialized
+    /// unless the table has columns of the same names.
+    /// Whole numbers can be deserialized into integers.
+    pub fn deserialize<'de, T: Deserialize<'de>>(&'de self) -> Result<Vec<T>> {
+        (0..self.rows.len())
+            .map(|index| self.deserialize_row(index))
+            .collect()
+    }
+
+    /// Deserialize a row into `T`, see `deserialize`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds
+    pub fn deserialize_row<'de, T: Deserialize<'de>>(&'de self, index: usize) -> Result<T> {
+        T::deserialize(RowDeserializer::new(self, &self.rows[index])).map_err(|err| {
+            IpfError::Deserialize {
+                row: index,
+                column: err.column,
+                message: err.message,
+            }
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Error {
+    column: Option<String>,
+    message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self {
+            column: None,
+            message: msg.to_string(),
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Self {
+            column: Some(field.into()),
+            message: "missing column".into(),
+        }
+    }
+}
+
+struct RowDeserializer<'de> {
+    fields: Vec<(&'de str, Value<'de>)>,
+}
+
+impl<'de> RowDeserializer<'de> {
+    fn new(table: &'de IesTable, row: &'de IesRow) -> Self {
+        let mut fields = Vec::with_capacity(row.cells.len() + 2);
+        if !row.names.contains_key("ClassID") {
+            fields.push(("ClassID", Value::Id(row.class_id)));
+        }
+        if !row.names.contains_key("ClassName") {
+            fields.push(("ClassName", Value::Str(&row.class_name)));
+        }
+        for (column, cell) in table.columns.iter().zip(&row.cells) {
+            let value = match cell {
+                IesCell::Int(number) => Value::Number(*number),
+                IesCell::Str(string) => Value::Str(string),
+            };
+            fields.push((&column.name1, value));
+        }
+        Self { fields }
+    }
+}
+
+impl<'de> Deserializer<'de> for RowDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, Error> {
+        visitor.visit_map(RowAccess {
+            fields: self.fields.into_iter(),
+            column: None,
+        })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct RowAccess<'de> {
+    fields: std::vec::IntoIter<(&'de str, Value<'de>)>,
+    column: Option<(&'de str, Value<'de>)>,
+}
+
+impl<'de> MapAccess<'de> for RowAccess<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> std::result::Result<Option<K::Value>, Error> {
+        match self.fields.next() {
+            Some((name, value)) => {
+                self.column = Some((name, value));
+                seed.deserialize(BorrowedStrDeserializer::new(name))
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> std::result::Result<V::Value, Error> {
+        let (name, value) = self
+            .column
+            .take()
+            .ok_or_else(|| de::Error::custom("value is missing"))?;
+        seed.deserialize(value).map_err(|mut err| {
+            err.column.get_or_insert_with(|| name.into());
+            err
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Value<'de> {
+    Number(f32),
+    Str(&'de str),
+    Id(u32),
+}
+
+impl Value<'_> {
+    fn unexpected(&self) -> Unexpected<'_> {
+        match self {
+            Value::Number(number) => Unexpected::Float((*number).into()),
+            Value::Str(string) => Unexpected::Str(string),
+            Value::Id(id) => Unexpected::Unsigned((*id).into()),
+        }
+    }
+
+    fn integer<T: TryFrom<i64> + FromStr>(&self) -> std::result::Result<T, Error> {
+        let integer = match self {
+            Value::Number(number) => whole(*number).and_then(|number| T::try_from(number).ok()),
+            Value::Str(string) => string.trim().parse().ok(),
+            Value::Id(id) => T::try_from((*id).into()).ok(),
+        };
+        integer.ok_or_else(|| {
+            de::Error::custom(format_args!(
+                "expected {}, found {}",
+                std::any::type_name::<T>(),
+                self.unexpected()
+            ))
+        })
+    }
+
+    fn float(&self) -> std::result::Result<f64, Error> {
+        match self {
+            Value::Number(number) => Ok((*number).into()),
+            Value::Str(string) => string
+                .trim()
+                .parse()
+                .map_err(|_| de::Error::invalid_type(self.unexpected(), &"a number")),
+            Value::Id(id) => Ok((*id).into()),
+        }
+    }
+}
+
+/// `number` as i64 if it is a whole number in the range of i64
+fn whole(number: f32) -> Option<i64> {
+    // i64::MIN and i64::MAX + 1 are exact in f32
+    let range = i64::MIN as f32..-(i64::MIN as f32);
+    (number.fract() == 0.0 && range.contains(&number)).then_some(number as i64)
+}
+
+macro_rules! deserialize_integer {
+    ($($method:ident => $visit:ident),* $(,)?) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, Error> {
+            visitor.$visit(self.integer()?)
+        }
+    )*};
+}
+
+impl<'de> Deserializer<'de> for Value<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, Error> {
+        match self {
+            Value::Number(number) => match whole(number) {
+                Some(integer) => visitor.visit_i64(integer),
+                None => visitor.visit_f32(number),
+            },
+            Value::Str(string) => visitor.visit_borrowed_str(string),
+            Value::Id(id) => visitor.visit_u32(id),
+        }
+    }
+
+    deserialize_integer! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, Error> {
+        visitor.visit_f32(self.float()? as f32)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, Error> {
+        visitor.visit_f64(self.float()?)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, Error> {
+        match self {
+            Value::Number(0.0) => visitor.visit_bool(false),
+            Value::Number(1.0) => visitor.visit_bool(true),
+            Value::Str(string) => match string.trim().parse() {
+                Ok(boolean) => visitor.visit_bool(boolean),
+                Err(_) => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+            },
+            _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, Error> {
+        match self {
+            Value::Str(string) => visitor.visit_borrowed_str(string),
+            _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> std::result::Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> std::result::Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> std::result::Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> std::result::Result<V::Value, Error> {
+        match self {
+            Value::Str(string) => visitor.visit_enum(string.into_deserializer()),
+            _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        i128 u128 char bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use crate::{
+        error::IpfError,
+        ies::{CsvOptions, IesColumn, IesTable},
+    };
+
+    fn table() -> IesTable {
+        let columns = vec![
+            IesColumn::new("Level", false),
+            IesColumn::new("Weight", false),
+            IesColumn::new("Name", true),
+            IesColumn::new("Flag", false),
+        ];
+        let csv = "ClassID,ClassName,Level,Weight,Name,Flag\n\
+                   10,Sword,3,2.5,Short,1\n\
+                   11,Shield,1e20,-1,,0\n";
+        IesTable::from_csv(csv.as_bytes(), "Item", columns, &CsvOptions::default()).unwrap()
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "PascalCase")]
+    struct Item {
+        #[serde(rename = "ClassID")]
+        class_id: u32,
+        class_name: String,
+        weight: f32,
+        name: Option<String>,
+        flag: bool,
+    }
+
+    #[test]
+    fn rows_into_structs() {
+        let table = table();
+        let items: Vec<Item> = table.deserialize().unwrap();
+        assert_eq!(
+            items,
+            [
+                Item {
+                    class_id: 10,
+                    class_name: "Sword".into(),
+                    weight: 2.5,
+                    name: Some("Short".into()),
+                    flag: true,
+                },
+                Item {
+                    class_id: 11,
+                    class_name: "Shield".into(),
+                    weight: -1.0,
+                    name: Some("".into()),
+                    flag: false,
+                },
+            ]
+        );
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    #[allow(dead_code)]
+    struct Integers {
+        level: i64,
+        weight: i32,
+    }
+
+    fn error_at(err: IpfError) -> (usize, Option<String>) {
+        match err {
+            IpfError::Deserialize { row, column, .. } => (row, column),
+            err => panic!("unexpected error {err}"),
+        }
+    }
+
+    #[test]
+    fn whole_numbers_into_integers() {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "PascalCase")]
+        struct Level {
+            level: u8,
+        }
+        assert_eq!(table().deserialize_row::<Level>(0).unwrap().level, 3);
+    }
+
+    #[test]
+    fn fraction_into_integer_fails() {
+        let err = table().deserialize_row::<Integers>(0).unwrap_err();
+        assert_eq!(error_at(err), (0, Some("Weight".into())));
+    }
+
+    #[test]
+    fn number_out_of_range_fails() {
+        let err = table().deserialize_row::<Integers>(1).unwrap_err();
+        assert_eq!(error_at(err), (1, Some("Level".into())));
+    }
+
+    #[test]
+    fn missing_column_fails() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Missing {
+            missing: String,
+        }
+        let err = table().deserialize::<Missing>().unwrap_err();
+        assert_eq!(error_at(err), (0, Some("missing".into())));
+    }
+}
@@ -0,0 +1,390 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Write as _},
+    io::Write,
+};
+
+use super::{IesCell, IesColumn, IesRow, IesTable};
+use crate::{error::Result, json};
+
+/// What identifies the same row in two versions of a table
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RowKey {
+    ClassId,
+    ClassName,
+}
+
+/// Column which both tables have, with another type
+#[derive(Clone, Copy, Debug)]
+pub struct ColumnChange<'a> {
+    pub old: &'a IesColumn,
+    pub new: &'a IesColumn,
+}
+
+/// Row which both tables have, with different values
+#[derive(Clone, Debug)]
+pub struct RowChange<'a> {
+    pub old: &'a IesRow,
+    pub new: &'a IesRow,
+    /// Cells of the columns both tables have, which have changed
+    pub cells: Vec<CellChange<'a>>,
+}
+
+/// Changed cell of a row
+#[derive(Clone, Copy, Debug)]
+pub struct CellChange<'a> {
+    pub column: &'a str,
+    pub old: &'a IesCell,
+    pub new: &'a IesCell,
+}
+
+/// Difference between two versions of a table, see `IesTable::diff`
+///
+/// `Display` writes a readable changelog
+#[derive(Debug)]
+pub struct IesDiff<'a> {
+    key: RowKey,
+    added_columns: Vec<&'a IesColumn>,
+    removed_columns: Vec<&'a IesColumn>,
+    changed_columns: Vec<ColumnChange<'a>>,
+    added_rows: Vec<&'a IesRow>,
+    removed_rows: Vec<&'a IesRow>,
+    changed_rows: Vec<RowChange<'a>>,
+}
+
+impl IesTable {
+    /// Compare this (old) table with `other` (new) table
+    ///
+    /// Columns are matched by name and rows by `key`. When rows have the same key,
+    /// they are matched in the order they appear in each table.
+    /// A row has changed if any of its cells in the columns both tables have,
+    /// its class id or its class name has changed.
+    pub fn diff<'a>(&'a self, other: &'a IesTable, key: RowKey) -> IesDiff<'a> {
+        let mut diff = IesDiff {
+            key,
+            added_columns: Vec::new(),
+            removed_columns: Vec::new(),
+            changed_columns: Vec::new(),
+            added_rows: Vec::new(),
+            removed_rows: Vec::new(),
+            changed_rows: Vec::new(),
+        };
+
+        for column in &self.columns {
+            if other.column(&column.name1).is_none() {
+                diff.removed_columns.push(column);
+            }
+        }
+        let mut common = Vec::new();
+        for (index, column) in other.columns.iter().enumerate() {
+            match self.column_index(&column.name1) {
+                Some(old) => {
+                    if self.columns[old].is_string() != column.is_string() {
+                        diff.changed_columns.push(ColumnChange {
+                            old: &self.columns[old],
+                            new: column,
+                        });
+                    }
+                    common.push((column.name1.as_str(), old, index));
+                }
+                None => diff.added_columns.push(column),
+            }
+        }
+
+        let old_rows = row_keys(&self.rows, key);
+        let mut matched = vec![false; self.rows.len()];
+        let mut occurrences: HashMap<String, usize> = HashMap::new();
+        for new in &other.rows {
+            let row_key = key.of(new);
+            let occurrence = occurrences.entry(row_key.clone()).or_default();
+            *occurrence += 1;
+            let old = match old_rows
+                .get(&row_key)
+                .and_then(|rows| rows.get(*occurrence - 1))
+            {
+                Some(old) => *old,
+                None => {
+                    diff.added_rows.push(new);
+                    continue;
+                }
+            };
+            matched[old] = true;
+            let old = &self.rows[old];
+
+            let cells: Vec<_> = common
+                .iter()
+                .filter(|(_, old_index, new_index)| {
+                    !same_cell(&old.cells[*old_index], &new.cells[*new_index])
+                })
+                .map(|(column, old_index, new_index)| CellChange {
+                    column,
+                    old: &old.cells[*old_index],
+                    new: &new.cells[*new_index],
+                })
+                .collect();
+            if !cells.is_empty() || old.class_id != new.class_id || old.class_name != new.class_name
+            {
+                diff.changed_rows.push(RowChange { old, new, cells });
+            }
+        }
+        diff.removed_rows = self
+            .rows
+            .iter()
+            .zip(matched)
+            .filter(|(_, matched)| !matched)
+            .map(|(row, _)| row)
+            .collect();
+        diff
+    }
+}
+
+impl RowKey {
+    fn of(self, row: &IesRow) -> String {
+        match self {
+            RowKey::ClassId => row.class_id.to_string(),
+            RowKey::ClassName => row.class_name.clone(),
+        }
+    }
+}
+
+/// key -> indices of rows of the key
+fn row_keys(rows: &[IesRow], key: RowKey) -> HashMap<String, Vec<usize>> {
+    let mut keys: HashMap<String, Vec<usize>> = HashMap::new();
+    for (index, row) in rows.iter().enumerate() {
+        keys.entry(key.of(row)).or_default().push(index);
+    }
+    keys
+}
+
+/// numbers are compared by their bits, so NaN is the same as NaN as it is stored
+fn same_cell(old: &IesCell, new: &IesCell) -> bool {
+    match (old, new) {
+        (IesCell::Int(old), IesCell::Int(new)) => old.to_bits() == new.to_bits(),
+        _ => old == new,
+    }
+}
+
+impl<'a> IesDiff<'a> {
+    /// Columns which only the new table has
+    pub fn added_columns(&self) -> &[&'a IesColumn] {
+        &self.added_columns
+    }
+
+    /// Columns which only the old table has
+    pub fn removed_columns(&self) -> &[&'a IesColumn] {
+        &self.removed_columns
+    }
+
+    /// Columns which have changed between string and number
+    pub fn changed_columns(&self) -> &[ColumnChange<'a>] {
+        &self.changed_columns
+    }
+
+    /// Rows which only the new table has, in its order
+    pub fn added_rows(&self) -> &[&'a IesRow] {
+        &self.added_rows
+    }
+
+    /// Rows which only the old table has, in its order
+    pub fn removed_rows(&self) -> &[&'a IesRow] {
+        &self.removed_rows
+    }
+
+    /// Rows which have changed, in the order of the new table
+    pub fn changed_rows(&self) -> &[RowChange<'a>] {
+        &self.changed_rows
+    }
+
+    /// Whether both tables have the same columns and rows
+    pub fn is_empty(&self) -> bool {
+        self.added_columns.is_empty()
+            && self.removed_columns.is_empty()
+            && self.changed_columns.is_empty()
+            && self.added_rows.is_empty()
+            && self.removed_rows.is_empty()
+            && self.changed_rows.is_empty()
+    }
+
+    /// Write the difference as json
+    ///
+    /// ```json
+    /// {
+    ///   "columns": {
+    ///     "added": [{ "name": "Weight", "string": false }],
+    ///     "removed": [],
+    ///     "changed": [{ "name": "Grade", "old_string": false, "new_string": true }]
+    ///   },
+    ///   "rows": {
+    ///     "added": [{ "class_id": 2, "class_name": "Shield" }],
+    ///     "removed": [],
+    ///     "changed": [{
+    ///       "old": { "class_id": 1, "class_name": "Sword" },
+    ///       "new": { "class_id": 1, "class_name": "Sword" },
+    ///       "cells": { "Level": { "old": 3, "new": 4 } }
+    ///     }]
+    ///   }
+    /// }
+    /// ```
+    pub fn write_json(&self, mut writer: impl Write) -> Result<()> {
+        let mut out = String::new();
+        out.push_str("{\"columns\":{\"added\":[");
+        json_columns(&mut out, &self.added_columns);
+        out.push_str("],\"removed\":[");
+        json_columns(&mut out, &self.removed_columns);
+        out.push_str("],\"changed\":[");
+        for (index, change) in self.changed_columns.iter().enumerate() {
+            if index != 0 {
+                out.push(',');
+            }
+            out.push_str("{\"name\":");
+            json::string(&mut out, &change.new.name1);
+            write!(
+                out,
+                ",\"old_string\":{},\"new_string\":{}}}",
+                change.old.is_string(),
+                change.new.is_string()
+            )
+            .unwrap();
+        }
+
+        out.push_str("]},\"rows\":{\"added\":[");
+        json_rows(&mut out, &self.added_rows);
+        out.push_str("],\"removed\":[");
+        json_rows(&mut out, &self.removed_rows);
+        out.push_str("],\"changed\":[");
+        for (index, change) in self.changed_rows.iter().enumerate() {
+            if index != 0 {
+                out.push(',');
+            }
+            out.push_str("{\"old\":");
+            json_row(&mut out, change.old);
+            out.push_str(",\"new\":");
+            json_row(&mut out, change.new);
+            out.push_str(",\"cells\":{");
+            for (index, cell) in change.cells.iter().enumerate() {
+                if index != 0 {
+                    out.push(',');
+                }
+                json::string(&mut out, cell.column);
+                out.push_str(":{\"old\":");
+                json_cell(&mut out, cell.old);
+                out.push_str(",\"new\":");
+                json_cell(&mut out, cell.new);
+                out.push('}');
+            }
+            out.push_str("}}");
+        }
+        out.push_str("]}}");
+        writer.write_all(out.as_bytes())?;
+        Ok(())
+    }
+
+    fn row_name(&self, row: &IesRow) -> String {
+        match self.key {
+            RowKey::ClassId if row.class_name.is_empty() => row.class_id.to_string(),
+            RowKey::ClassId => format!("{} ({})", row.class_id, row.class_name),
+            RowKey::ClassName => format!("{} ({})", row.class_name, row.class_id),
+        }
+    }
+}
+
+impl fmt::Display for IesDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = |column: &IesColumn| {
+            if column.is_string() {
+                "string"
+            } else {
+                "number"
+            }
+        };
+        for column in &self.added_columns {
+            writeln!(f, "+ column {} ({})", column.name1, kind(column))?;
+        }
+        for column in &self.removed_columns {
+            writeln!(f, "- column {} ({})", column.name1, kind(column))?;
+        }
+        for change in &self.changed_columns {
+            writeln!(
+                f,
+                "~ column {} ({} -> {})",
+                change.new.name1,
+                kind(change.old),
+                kind(change.new)
+            )?;
+        }
+        for row in &self.added_rows {
+            writeln!(f, "+ row {}", self.row_name(row))?;
+        }
+        for row in &self.removed_rows {
+            writeln!(f, "- row {}", self.row_name(row))?;
+        }
+        for change in &self.changed_rows {
+            writeln!(f, "~ row {}", self.row_name(change.new))?;
+            if change.old.class_id != change.new.class_id {
+                writeln!(
+                    f,
+                    "    ClassID: {} -> {}",
+                    change.old.class_id, change.new.class_id
+                )?;
+            }
+            if change.old.class_name != change.new.class_name {
+                writeln!(
+                    f,
+                    "    ClassName: {:?} -> {:?}",
+                    change.old.class_name, change.new.class_name
+                )?;
+            }
+            for cell in &change.cells {
+                writeln!(
+                    f,
+                    "    {}: {} -> {}",
+                    cell.column,
+                    readable(cell.old),
+                    readable(cell.new)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// numbers as they are, strings quoted
+fn readable(cell: &IesCell) -> String {
+    match cell {
+        IesCell::Int(number) => number.to_string(),
+        IesCell::Str(string) => format!("{string:?}"),
+    }
+}
+
+fn json_columns(out: &mut String, columns: &[&IesColumn]) {
+    for (index, column) in columns.iter().enumerate() {
+        if index != 0 {
+            out.push(',');
+        }
+        out.push_str("{\"name\":");
+        json::string(out, &column.name1);
+        write!(out, ",\"string\":{}}}", column.is_string()).unwrap();
+    }
+}
+
+fn json_rows(out: &mut String, rows: &[&IesRow]) {
+    for (index, row) in rows.iter().enumerate() {
+        if index != 0 {
+            out.push(',');
+        }
+        json_row(out, row);
+    }
+}
+
+fn json_row(out: &mut String, row: &IesRow) {
+    write!(out, "{{\"class_id\":{},\"class_name\":", row.class_id).unwrap();
+    json::string(out, &row.class_name);
+    out.push('}');
+}
+
+fn json_cell(out: &mut String, cell: &IesCell) {
+    match cell {
+        IesCell::Int(number) => json::number(out, *number),
+        IesCell::Str(string) => json::string(out, string),
+    }
+}
@@ -0,0 +1,68 @@
+use std::{fmt::Write as _, io::Write};
+
+use super::{IesCell, IesTable};
+use crate::{error::Result, json};
+
+impl IesTable {
+    /// Write the table as json
+    ///
+    /// ```json
+    /// {
+    ///   "name": "Item",
+    ///   "columns": [{ "name": "Level", "name2": "Level", "string": false, "order": 0 }],
+    ///   "rows": [{ "class_id": 1, "class_name": "Sword", "string_flags": [], "cells": { "Level": 3 } }]
+    /// }
+    /// ```
+    ///
+    /// Numbers which are not finite are written as null
+    pub fn write_json(&self, mut writer: impl Write) -> Result<()> {
+        let mut out = String::new();
+        out.push_str("{\"name\":");
+        json::string(&mut out, &self.header.name);
+
+        out.push_str(",\"columns\":[");
+        for (index, column) in self.columns.iter().enumerate() {
+            if index != 0 {
+                out.push(',');
+            }
+            out.push_str("{\"name\":");
+            json::string(&mut out, &column.name1);
+            out.push_str(",\"name2\":");
+            json::string(&mut out, &column.name2);
+            write!(
+                out,
+                ",\"string\":{},\"order\":{}}}",
+                column.is_string(),
+                column.order
+            )
+            .unwrap();
+        }
+        out.push_str("],\"rows\":[");
+        writer.write_all(out.as_bytes())?;
+
+        for (index, row) in self.rows.iter().enumerate() {
+            out.clear();
+            if index != 0 {
+                out.push(',');
+            }
+            write!(out, "{{\"class_id\":{},\"class_name\":", row.class_id).unwrap();
+            json::string(&mut out, &row.class_name);
+            write!(out, ",\"string_flags\":{:?},\"cells\":{{", row.string_flags).unwrap();
+            for (index, (column, cell)) in self.columns.iter().zip(&row.cells).enumerate() {
+                if index != 0 {
+                    out.push(',');
+                }
+                json::string(&mut out, &column.name1);
+                out.push(':');
+                match cell {
+                    IesCell::Int(number) => json::number(&mut out, *number),
+                    IesCell::Str(string) => json::string(&mut out, string),
+                }
+            }
+            out.push_str("}}");
+            writer.write_all(out.as_bytes())?;
+        }
+        writer.write_all(b"]}")?;
+        Ok(())
+    }
+}
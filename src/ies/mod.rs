@@ -0,0 +1,814 @@
+//! Reading and writing of ies files, the data tables of the game
+
+use crate::error::{IpfError, Result};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+    sync::Arc,
+};
+
+mod csv;
+#[cfg(feature = "serde")]
+mod de;
+mod diff;
+mod json;
+
+pub use csv::{CsvOptions, NumberFormat};
+pub use diff::{CellChange, ColumnChange, IesDiff, RowChange, RowKey};
+
+pub(crate) struct IesReader<R: Read + Seek> {
+    reader: R,
+    cursor: Option<Cursor<Vec<u8>>>,
+}
+
+impl<R: Read + Seek> IesReader<R> {
+    pub fn new(reader: R) -> Self {
+        IesReader {
+            reader,
+            cursor: None,
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for IesReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.cursor.is_none() {
+            let mut buffer = Vec::new();
+            IesTable::parse(&mut self.reader)?.write_csv(&mut buffer, &CsvOptions::default())?;
+            self.cursor = Some(Cursor::new(buffer));
+        }
+        self.cursor.as_mut().unwrap().read(buf)
+    }
+}
+
+/// A data table
+///
+/// Number columns come first, then string columns, each sorted by their order
+pub struct IesTable {
+    header: IesHeader,
+    columns: Vec<IesColumn>,
+    rows: Vec<IesRow>,
+    /// indices of `columns` in the order they are stored in the file
+    file_order: Vec<usize>,
+}
+
+/// csv with the default `CsvOptions`
+impl fmt::Display for IesTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut buffer = Vec::new();
+        self.write_csv(&mut buffer, &CsvOptions::default())
+            .map_err(|_| fmt::Error)?;
+        f.write_str(&String::from_utf8_lossy(&buffer))
+    }
+}
+
+impl IesTable {
+    /// Read and parse an ies file
+    pub fn parse(mut reader: impl Read + Seek) -> Result<Self> {
+        let header = IesHeader::parse(&mut reader)?;
+
+        let mut int_columns = Vec::new();
+        let mut str_columns = Vec::new();
+        reader.seek(SeekFrom::Start(header.column_offset.into()))?;
+
+        for index in 0..header.column_count {
+            let column = IesColumn::parse(&mut reader)?;
+            if column.is_string() {
+                str_columns.push((index, column));
+            } else {
+                int_columns.push((index, column));
+            }
+        }
+        if int_columns.len() != header.int_column_count.into()
+            || str_columns.len() != header.str_column_count.into()
+        {
+            return Err(IpfError::InvalidArchive(
+                "Column count does not match the ies header",
+            ));
+        }
+        int_columns.sort_by_key(|(_, column)| column.order);
+        str_columns.sort_by_key(|(_, column)| column.order);
+        int_columns.extend(str_columns);
+
+        let mut file_order = vec![0; int_columns.len()];
+        for (sorted, (index, _)) in int_columns.iter().enumerate() {
+            file_order[usize::from(*index)] = sorted;
+        }
+        let columns: Vec<IesColumn> = int_columns.into_iter().map(|(_, column)| column).collect();
+
+        let names = Arc::new(column_names(&columns));
+        let mut rows = Vec::new();
+        reader.seek(SeekFrom::Start(header.row_offset.into()))?;
+
+        for _i in 0..header.row_count {
+            let row = IesRow::parse(
+                &mut reader,
+                header.int_column_count,
+                header.str_column_count,
+                &names,
+            )?;
+            rows.push(row);
+        }
+
+        Ok(Self {
+            header,
+            columns,
+            rows,
+            file_order,
+        })
+    }
+
+    /// Write the table as an ies file
+    ///
+    /// A table which is parsed and written back without changes is byte-identical to the original
+    pub fn write_to(&self, mut writer: impl Write) -> Result<()> {
+        let mut columns = Vec::with_capacity(self.columns.len() * IesColumn::SIZE);
+        for index in &self.file_order {
+            self.columns[*index].write_to(&mut columns)?;
+        }
+
+        let mut rows = Vec::new();
+        for row in &self.rows {
+            row.write_to(&mut rows, &self.columns)?;
+        }
+
+        let count = |is_string: bool| {
+            self.columns
+                .iter()
+                .filter(|column| column.is_string() == is_string)
+                .count()
+        };
+        let header = IesHeader {
+            name: self.header.name.clone(),
+            unknown1: self.header.unknown1,
+            column_offset: (IesHeader::SIZE + self.header.padding.len()) as u32,
+            row_offset: (IesHeader::SIZE + self.header.padding.len() + columns.len()) as u32,
+            file_size: (IesHeader::SIZE + self.header.padding.len() + columns.len() + rows.len())
+                .try_into()
+                .map_err(|_| IpfError::InvalidArchive("Ies file can not be larger than 4 GiB"))?,
+            unknown2: self.header.unknown2,
+            row_count: to_u16(self.rows.len(), "Too many rows in ies table")?,
+            column_count: to_u16(self.columns.len(), "Too many columns in ies table")?,
+            int_column_count: count(false) as u16,
+            str_column_count: count(true) as u16,
+            padding: self.header.padding.clone(),
+        };
+
+        writer.write_all(&header.into_bytes()?)?;
+        writer.write_all(&columns)?;
+        writer.write_all(&rows)?;
+        Ok(())
+    }
+
+    /// Name of the table, e.g. Item
+    pub fn name(&self) -> &str {
+        &self.header.name
+    }
+
+    /// Columns, in the same order as cells of each row
+    pub fn columns(&self) -> &[IesColumn] {
+        &self.columns
+    }
+
+    /// Get a column by its name
+    pub fn column(&self, name: &str) -> Option<&IesColumn> {
+        self.column_index(name).map(|index| &self.columns[index])
+    }
+
+    /// Get the position of a column by its name
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name1 == name)
+    }
+
+    /// Rows of the table
+    pub fn rows(&self) -> &[IesRow] {
+        &self.rows
+    }
+
+    /// Rows of the table, to change their cells
+    pub fn rows_mut(&mut self) -> &mut [IesRow] {
+        &mut self.rows
+    }
+
+    /// Add a row at the end of the table
+    ///
+    /// `cells` must be in the same order and of the same types as `columns`
+    pub fn push_row(
+        &mut self,
+        class_id: u32,
+        class_name: impl Into<String>,
+        cells: Vec<IesCell>,
+    ) -> Result<()> {
+        check_cells(&self.columns, &cells)?;
+        let names = match self.rows.first() {
+            Some(row) => Arc::clone(&row.names),
+            None => Arc::new(column_names(&self.columns)),
+        };
+        self.rows.push(IesRow {
+            class_id,
+            class_name: class_name.into(),
+            cells,
+            string_flags: vec![0; self.columns.iter().filter(|c| c.is_string()).count()],
+            names,
+        });
+        Ok(())
+    }
+
+    /// Remove a row and return it
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds
+    pub fn remove_row(&mut self, index: usize) -> IesRow {
+        self.rows.remove(index)
+    }
+
+    /// Number of rows
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Whether the table has no rows
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
+
+fn check_cells(columns: &[IesColumn], cells: &[IesCell]) -> Result<()> {
+    if columns.len() != cells.len()
+        || columns
+            .iter()
+            .zip(cells)
+            .any(|(column, cell)| column.is_string() != matches!(cell, IesCell::Str(_)))
+    {
+        return Err(IpfError::InvalidArchive(
+            "Cells do not match the columns of ies table",
+        ));
+    }
+    Ok(())
+}
+
+fn to_u16(value: usize, message: &'static str) -> Result<u16> {
+    value
+        .try_into()
+        .map_err(|_| IpfError::InvalidArchive(message))
+}
+
+fn column_names(columns: &[IesColumn]) -> HashMap<String, usize> {
+    columns
+        .iter()
+        .enumerate()
+        .map(|(index, column)| (column.name1.clone(), index))
+        .collect()
+}
+
+struct IesHeader {
+    name: String, // 128 bytes
+    unknown1: u32,
+    column_offset: u32,
+    row_offset: u32,
+    file_size: u32,
+    unknown2: u16,
+    row_count: u16,
+    column_count: u16,
+    int_column_count: u16,
+    str_column_count: u16,
+    /// unknown bytes between the header and the columns, usually 2 bytes
+    padding: Vec<u8>,
+}
+
+impl IesHeader {
+    const SIZE: usize = 128 + 4 * 4 + 2 * 5;
+
+    /// header of a new table, offsets and counts are filled when writing
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            unknown1: 0,
+            column_offset: 0,
+            row_offset: 0,
+            file_size: 0,
+            unknown2: 0,
+            row_count: 0,
+            column_count: 0,
+            int_column_count: 0,
+            str_column_count: 0,
+            padding: vec![0; 2],
+        }
+    }
+
+    pub(crate) fn parse(mut reader: impl Read + Seek) -> Result<Self> {
+        if reader.rewind().is_err() {
+            return Err(IpfError::InvalidArchive("Failed to rewind the reader"));
+        }
+        let mut buffer = [0u8; Self::SIZE];
+        reader.read_exact(&mut buffer)?;
+
+        let name = match String::from_utf8(buffer[0..128].into()) {
+            Ok(string) => string.trim_end_matches(char::from(0)).into(),
+            Err(err) => return Err(IpfError::Encoding(err)),
+        };
+        let unknown1 = u32::from_le_bytes(buffer[128..132].try_into().unwrap());
+        let offset_hint1 = u32::from_le_bytes(buffer[132..136].try_into().unwrap());
+        let offset_hint2 = u32::from_le_bytes(buffer[136..140].try_into().unwrap());
+        let file_size = u32::from_le_bytes(buffer[140..144].try_into().unwrap());
+
+        let (column_offset, row_offset) = match file_size
+            .checked_sub(offset_hint2)
+            .and_then(|row_offset| Some((row_offset.checked_sub(offset_hint1)?, row_offset)))
+        {
+            Some(offsets) => offsets,
+            None => return Err(IpfError::InvalidArchive("Invalid offsets in ies header")),
+        };
+
+        let unknown2 = u16::from_le_bytes(buffer[144..146].try_into().unwrap());
+        let row_count = u16::from_le_bytes(buffer[146..148].try_into().unwrap());
+        let column_count = u16::from_le_bytes(buffer[148..150].try_into().unwrap());
+        let int_column_count = u16::from_le_bytes(buffer[150..152].try_into().unwrap());
+        let str_column_count = u16::from_le_bytes(buffer[152..154].try_into().unwrap());
+
+        let padding_length = match (column_offset as usize).checked_sub(Self::SIZE) {
+            Some(length) => length,
+            None => return Err(IpfError::InvalidArchive("Invalid offsets in ies header")),
+        };
+        let mut padding = vec![0u8; padding_length];
+        reader.read_exact(&mut padding)?;
+
+        Ok(Self {
+            name,
+            unknown1,
+            column_offset,
+            row_offset,
+            file_size,
+            unknown2,
+            row_count,
+            column_count,
+            int_column_count,
+            str_column_count,
+            padding,
+        })
+    }
+
+    fn into_bytes(self) -> Result<Vec<u8>> {
+        if self.name.len() > 128 {
+            return Err(IpfError::InvalidArchive(
+                "Name of ies table is longer than 128 bytes",
+            ));
+        }
+        let mut vec = Vec::with_capacity(Self::SIZE + self.padding.len());
+        vec.extend_from_slice(self.name.as_bytes());
+        vec.resize(128, 0);
+        vec.extend_from_slice(&self.unknown1.to_le_bytes());
+        vec.extend_from_slice(&(self.row_offset - self.column_offset).to_le_bytes());
+        vec.extend_from_slice(&(self.file_size - self.row_offset).to_le_bytes());
+        vec.extend_from_slice(&self.file_size.to_le_bytes());
+        vec.extend_from_slice(&self.unknown2.to_le_bytes());
+        vec.extend_from_slice(&self.row_count.to_le_bytes());
+        vec.extend_from_slice(&self.column_count.to_le_bytes());
+        vec.extend_from_slice(&self.int_column_count.to_le_bytes());
+        vec.extend_from_slice(&self.str_column_count.to_le_bytes());
+        vec.extend_from_slice(&self.padding);
+        Ok(vec)
+    }
+}
+
+/// A column of ies table
+#[derive(Clone, Debug)]
+pub struct IesColumn {
+    name1: String,
+    /// sometimes it is name1 with prefix "CT_", but mostly it is name1
+    name2: String,
+    /// 0 for number, otherwise string
+    kind: u8,
+    unknown1: [u8; 5],
+    order: u16,
+}
+
+impl fmt::Display for IesColumn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name1)
+    }
+}
+
+impl IesColumn {
+    const SIZE: usize = 64 + 64 + 1 + 5 + 2;
+
+    /// Create a column of a new table
+    ///
+    /// Order of the column is decided by its position when the table is created
+    pub fn new(name: impl Into<String>, is_string: bool) -> Self {
+        let name1 = name.into();
+        Self {
+            name2: name1.clone(),
+            name1,
+            kind: is_string.into(),
+            unknown1: [0; 5],
+            order: 0,
+        }
+    }
+
+    ///  seek before calling this function
+    pub(crate) fn parse(mut reader: impl Read + Seek) -> Result<Self> {
+        let mut buffer = [0u8; Self::SIZE];
+        reader.read_exact(&mut buffer)?;
+
+        let name1 = decrypt(buffer[0..64].into())?;
+        let name2 = decrypt(buffer[64..128].into())?;
+        let kind = buffer[128];
+        let unknown1 = buffer[129..134].try_into().unwrap();
+        let order = u16::from_le_bytes(buffer[134..136].try_into().unwrap());
+
+        Ok(Self {
+            name1,
+            name2,
+            kind,
+            unknown1,
+            order,
+        })
+    }
+
+    fn write_to(&self, buffer: &mut Vec<u8>) -> Result<()> {
+        for name in [&self.name1, &self.name2] {
+            if name.len() > 64 {
+                return Err(IpfError::InvalidArchive(
+                    "Column name is longer than 64 bytes",
+                ));
+            }
+            let start = buffer.len();
+            buffer.extend(encrypt(name));
+            buffer.resize(start + 64, 0);
+        }
+        buffer.push(self.kind);
+        buffer.extend_from_slice(&self.unknown1);
+        buffer.extend_from_slice(&self.order.to_le_bytes());
+        Ok(())
+    }
+
+    /// Name of the column, e.g. Level
+    pub fn name(&self) -> &str {
+        &self.name1
+    }
+
+    /// Second name of the column.
+    /// Sometimes it is `name` with prefix "CT_", but mostly it is same as `name`
+    pub fn name2(&self) -> &str {
+        &self.name2
+    }
+
+    /// Whether cells of the column are `IesCell::Str`
+    pub fn is_string(&self) -> bool {
+        self.kind != 0
+    }
+
+    /// Whether cells of the column are `IesCell::Int`
+    pub fn is_number(&self) -> bool {
+        self.kind == 0
+    }
+
+    /// Order of the column among the columns of the same type
+    pub fn order(&self) -> u16 {
+        self.order
+    }
+}
+
+/// A row of ies table
+#[derive(Clone, Debug)]
+pub struct IesRow {
+    class_id: u32,
+    /// every row has a *additional* class name
+    class_name: String,
+    cells: Vec<IesCell>,
+    /// one byte per string column, which follows the cells
+    string_flags: Vec<u8>,
+    names: Arc<HashMap<String, usize>>,
+}
+
+impl IesRow {
+    ///  seek before calling this function
+    fn parse(
+        mut reader: impl Read + Seek,
+        int_column: u16,
+        string_column: u16,
+        names: &Arc<HashMap<String, usize>>,
+    ) -> Result<Self> {
+        let mut buffer = [0u8; 6];
+        reader.read_exact(&mut buffer)?;
+        let class_id = u32::from_le_bytes(buffer[0..4].try_into().unwrap());
+        let class_name_length = u16::from_le_bytes(buffer[4..6].try_into().unwrap());
+
+        let mut buffer = vec![0u8; class_name_length.into()];
+        reader.read_exact(&mut buffer)?;
+        let class_name = decrypt(buffer)?;
+
+        let mut cells = Vec::new();
+
+        for _i in 0..int_column {
+            let cell = IesCell::parse_int(&mut reader)?;
+            cells.push(cell);
+        }
+
+        for _i in 0..string_column {
+            let cell = IesCell::parse_string(&mut reader)?;
+            cells.push(cell);
+        }
+
+        // one unknown byte for each string column
+        let mut string_flags = vec![0u8; string_column.into()];
+        reader.read_exact(&mut string_flags)?;
+
+        Ok(Self {
+            class_id,
+            class_name,
+            cells,
+            string_flags,
+            names: Arc::clone(names),
+        })
+    }
+
+    fn write_to(&self, buffer: &mut Vec<u8>, columns: &[IesColumn]) -> Result<()> {
+        check_cells(columns, &self.cells)?;
+        if self.string_flags.len() != columns.iter().filter(|c| c.is_string()).count() {
+            return Err(IpfError::InvalidArchive(
+                "Cells do not match the columns of ies table",
+            ));
+        }
+
+        buffer.extend_from_slice(&self.class_id.to_le_bytes());
+        write_string(buffer, &self.class_name)?;
+        for cell in &self.cells {
+            match cell {
+                IesCell::Int(value) => buffer.extend_from_slice(&value.to_le_bytes()),
+                IesCell::Str(value) => write_string(buffer, value)?,
+            }
+        }
+        buffer.extend_from_slice(&self.string_flags);
+        Ok(())
+    }
+
+    /// ID of the row, which is unique in the table
+    pub fn class_id(&self) -> u32 {
+        self.class_id
+    }
+
+    /// Name of the row, e.g. Sword_01
+    pub fn class_name(&self) -> &str {
+        &self.class_name
+    }
+
+    /// Cells, in the same order as `IesTable::columns`
+    pub fn cells(&self) -> &[IesCell] {
+        &self.cells
+    }
+
+    /// Get a cell by its column name
+    pub fn get(&self, column: &str) -> Option<&IesCell> {
+        self.names.get(column).map(|index| &self.cells[*index])
+    }
+
+    /// Get a mutable cell by its column name
+    ///
+    /// Type of the cell must not be changed, or writing the table fails
+    pub fn get_mut(&mut self, column: &str) -> Option<&mut IesCell> {
+        self.names.get(column).map(|index| &mut self.cells[*index])
+    }
+
+    /// Unknown bytes of the row, one for each string column.
+    /// These are kept as is when the table is written
+    pub fn string_flags(&self) -> &[u8] {
+        &self.string_flags
+    }
+
+    pub fn string_flags_mut(&mut self) -> &mut [u8] {
+        &mut self.string_flags
+    }
+
+    pub fn set_class_id(&mut self, class_id: u32) {
+        self.class_id = class_id;
+    }
+
+    pub fn set_class_name(&mut self, class_name: impl Into<String>) {
+        self.class_name = class_name.into();
+    }
+
+    /// Get a string cell by its column name
+    ///
+    /// Returns `None` if there is no such column or the column is not a string column
+    pub fn get_str(&self, column: &str) -> Option<&str> {
+        self.get(column).and_then(IesCell::as_str)
+    }
+
+    /// Get a number cell by its column name
+    ///
+    /// Returns `None` if there is no such column or the column is not a number column
+    pub fn get_f32(&self, column: &str) -> Option<f32> {
+        self.get(column).and_then(IesCell::as_f32)
+    }
+}
+
+/// A cell of ies table
+#[derive(Clone, Debug, PartialEq)]
+pub enum IesCell {
+    /// Every number is stored as f32
+    Int(f32),
+    Str(String),
+}
+
+impl fmt::Display for IesCell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(value) => write!(f, "{value}"),
+            Self::Str(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl IesCell {
+    fn parse_int(mut reader: impl Read + Seek) -> Result<Self> {
+        let mut buffer = [0u8; 4];
+        reader.read_exact(&mut buffer)?;
+        let value = f32::from_le_bytes(buffer);
+        Ok(Self::Int(value))
+    }
+
+    fn parse_string(mut reader: impl Read + Seek) -> Result<Self> {
+        let mut buffer = [0u8; 2];
+        reader.read_exact(&mut buffer)?;
+        let length = u16::from_le_bytes(buffer);
+
+        let mut buffer = vec![0; length as usize];
+        reader.read_exact(&mut buffer)?;
+        let string = decrypt(buffer)?;
+
+        Ok(Self::Str(string))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Str(value) => Some(value),
+            Self::Int(_) => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Self::Int(value) => Some(*value),
+            Self::Str(_) => None,
+        }
+    }
+}
+
+/// write length prefixed, encrypted string
+fn write_string(buffer: &mut Vec<u8>, string: &str) -> Result<()> {
+    buffer
+        .extend_from_slice(&to_u16(string.len(), "String in ies table is too long")?.to_le_bytes());
+    buffer.extend(encrypt(string));
+    Ok(())
+}
+
+fn encrypt(string: &str) -> impl Iterator<Item = u8> + '_ {
+    string.bytes().map(|byte| byte ^ 1)
+}
+
+fn decrypt(mut bytes: Vec<u8>) -> Result<String> {
+    for (idx, byte) in bytes.iter_mut().enumerate() {
+        // trim NUL character
+        if *byte == 0 {
+            bytes.resize(idx, 0);
+            break;
+        }
+        *byte ^= 1;
+    }
+    match String::from_utf8(bytes) {
+        Ok(string) => Ok(string),
+        Err(err) => Err(IpfError::Encoding(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{CsvOptions, IesColumn, IesHeader, IesTable};
+
+    fn write(table: &IesTable) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        table.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn column(name1: &str, name2: &str, kind: u8, unknown: u8, order: u16) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for name in [name1, name2] {
+            let start = bytes.len();
+            bytes.extend(name.bytes().map(|byte| byte ^ 1));
+            bytes.resize(start + 64, 0);
+        }
+        bytes.push(kind);
+        bytes.extend_from_slice(&[unknown, 0, unknown, 0, 1]);
+        bytes.extend_from_slice(&order.to_le_bytes());
+        bytes
+    }
+
+    fn string(bytes: &mut Vec<u8>, string: &str) {
+        bytes.extend_from_slice(&(string.len() as u16).to_le_bytes());
+        bytes.extend(string.bytes().map(|byte| byte ^ 1));
+    }
+
+    fn row(class_id: u32, class_name: &str, numbers: [f32; 2], strings: [&str; 2]) -> Vec<u8> {
+        let mut bytes = class_id.to_le_bytes().to_vec();
+        string(&mut bytes, class_name);
+        for number in numbers {
+            bytes.extend_from_slice(&number.to_le_bytes());
+        }
+        for value in strings {
+            string(&mut bytes, value);
+        }
+        bytes.extend_from_slice(&[1, 0]);
+        bytes
+    }
+
+    /// ies file as the game stores it, with columns out of order and unknown fields set
+    fn fixture() -> Vec<u8> {
+        let padding = [0x07, 0x08, 0x09];
+        let columns = [
+            column("Name", "Name", 1, 0x11, 0),
+            column("Weight", "Weight", 0, 0x22, 1),
+            column("Level", "Level", 0, 0x33, 0),
+            column("Desc", "CT_Desc", 2, 0x44, 1),
+        ]
+        .concat();
+        let rows = [
+            row(1, "Sword", [3.0, 2.5], ["Sword", "Short, sharp"]),
+            row(5, "Shield", [f32::NAN, -0.0], ["", "검"]),
+        ]
+        .concat();
+
+        let column_offset = (IesHeader::SIZE + padding.len()) as u32;
+        let row_offset = column_offset + columns.len() as u32;
+        let file_size = row_offset + rows.len() as u32;
+        let mut bytes = b"Item".to_vec();
+        bytes.resize(128, 0);
+        for field in [
+            0xDEAD_BEEF,
+            row_offset - column_offset,
+            file_size - row_offset,
+            file_size,
+        ] {
+            bytes.extend_from_slice(&u32::to_le_bytes(field));
+        }
+        for field in [0x1234u16, 2, 4, 2, 2] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.extend_from_slice(&padding);
+        bytes.extend(columns);
+        bytes.extend(rows);
+        bytes
+    }
+
+    #[test]
+    fn fixture_writes_back_byte_identical() {
+        let bytes = fixture();
+        let table = IesTable::parse(Cursor::new(&bytes)).unwrap();
+        assert_eq!(write(&table), bytes);
+
+        let names: Vec<_> = table.columns().iter().map(IesColumn::name).collect();
+        assert_eq!(names, ["Level", "Weight", "Name", "Desc"]);
+        assert_eq!(table.column("Desc").unwrap().name2(), "CT_Desc");
+        let row = &table.rows()[1];
+        assert_eq!((row.class_id(), row.class_name()), (5, "Shield"));
+        assert_eq!(row.get_str("Desc"), Some("검"));
+        assert_eq!(row.string_flags(), [1, 0]);
+    }
+
+    #[test]
+    fn parse_and_write_back_is_byte_identical() {
+        let csv = "ClassID,ClassName,Level,Name,Weight,Desc,StringFlags\r\n\
+                   1,Sword,3,\"Short, sharp\",2.5,검,0001\r\n\
+                   7,Shield,NaN,\"\"\"Round\"\"\",-0,,ff00\r\n";
+        let columns = vec![
+            IesColumn::new("Level", false),
+            IesColumn::new("Name", true),
+            IesColumn::new("Weight", false),
+            IesColumn::new("Desc", true),
+        ];
+        let table =
+            IesTable::from_csv(csv.as_bytes(), "Item", columns, &CsvOptions::default()).unwrap();
+
+        let first = write(&table);
+        let parsed = IesTable::parse(Cursor::new(&first)).unwrap();
+        let second = write(&parsed);
+        assert_eq!(first, second);
+        assert_eq!(
+            write(&IesTable::parse(Cursor::new(&second)).unwrap()),
+            second
+        );
+
+        assert_eq!(parsed.name(), "Item");
+        assert_eq!(parsed.rows()[0].class_name(), "Sword");
+        assert_eq!(parsed.rows()[0].get_str("Name"), Some("Short, sharp"));
+        assert_eq!(parsed.rows()[1].class_id(), 7);
+        assert!(parsed.rows()[1].get_f32("Level").unwrap().is_nan());
+        assert_eq!(parsed.rows()[1].string_flags(), [0xff, 0x00]);
+    }
+}
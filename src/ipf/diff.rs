@@ -0,0 +1,166 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Write as _},
+    io::{Read, Seek, Write},
+};
+
+use super::IpfArchive;
+use crate::{entry::EntryInfo, error::Result, json};
+
+/// Entry which both archives have, with different contents
+#[derive(Clone, Copy, Debug)]
+pub struct EntryChange<'a> {
+    pub old: EntryInfo<'a>,
+    pub new: EntryInfo<'a>,
+}
+
+/// Difference between two archives, see `IpfArchive::diff`
+///
+/// `Display` writes one line per entry, like `A xml.ipf/item.xml`
+/// with `A` for added, `D` for removed and `M` for modified entries.
+#[derive(Debug)]
+pub struct ArchiveDiff<'a> {
+    added: Vec<EntryInfo<'a>>,
+    removed: Vec<EntryInfo<'a>>,
+    modified: Vec<EntryChange<'a>>,
+}
+
+impl<R: Read + Seek> IpfArchive<R> {
+    /// Compare entries of this (old) archive with `other` (new) archive
+    ///
+    /// Entries are matched by `archive_name/path` and compared by crc32 and sizes,
+    /// so no data is read
+    pub fn diff<'a, P: Read + Seek>(&'a self, other: &'a IpfArchive<P>) -> ArchiveDiff<'a> {
+        let old: HashMap<_, _> = self
+            .entries()
+            .map(|entry| (entry.header.key(), entry))
+            .collect();
+        let new: HashMap<_, _> = other
+            .entries()
+            .map(|entry| (entry.header.key(), entry))
+            .collect();
+
+        let mut diff = ArchiveDiff {
+            added: Vec::new(),
+            removed: Vec::new(),
+            modified: Vec::new(),
+        };
+        for entry in self.entries() {
+            if !new.contains_key(&entry.header.key()) {
+                diff.removed.push(entry);
+            }
+        }
+        for entry in other.entries() {
+            match old.get(&entry.header.key()) {
+                None => diff.added.push(entry),
+                Some(old)
+                    if old.crc32() != entry.crc32()
+                        || old.compressed_size() != entry.compressed_size()
+                        || old.uncompressed_size() != entry.uncompressed_size() =>
+                {
+                    diff.modified.push(EntryChange {
+                        old: *old,
+                        new: entry,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        diff
+    }
+}
+
+impl<'a> ArchiveDiff<'a> {
+    /// Entries which only the new archive has, in its order
+    pub fn added(&self) -> &[EntryInfo<'a>] {
+        &self.added
+    }
+
+    /// Entries which only the old archive has, in its order
+    pub fn removed(&self) -> &[EntryInfo<'a>] {
+        &self.removed
+    }
+
+    /// Entries which have changed, in the order of the new archive
+    pub fn modified(&self) -> &[EntryChange<'a>] {
+        &self.modified
+    }
+
+    /// Whether both archives have the same entries
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+
+    /// Write the difference as json
+    ///
+    /// ```json
+    /// {
+    ///   "added": [{ "path": "xml.ipf/new.xml", "crc32": 1, "compressed_size": 2, "uncompressed_size": 3 }],
+    ///   "removed": [],
+    ///   "modified": [{ "old": { "path": "xml.ipf/item.xml", ... }, "new": { ... } }]
+    /// }
+    /// ```
+    pub fn write_json(&self, mut writer: impl Write) -> Result<()> {
+        let mut out = String::new();
+        for (name, entries) in [("added", &self.added), ("removed", &self.removed)] {
+            out.push_str(if out.is_empty() { "{" } else { "," });
+            json::string(&mut out, name);
+            out.push_str(":[");
+            for (index, entry) in entries.iter().enumerate() {
+                if index != 0 {
+                    out.push(',');
+                }
+                json_entry(&mut out, entry);
+            }
+            out.push(']');
+        }
+        out.push_str(",\"modified\":[");
+        for (index, change) in self.modified.iter().enumerate() {
+            if index != 0 {
+                out.push(',');
+            }
+            out.push_str("{\"old\":");
+            json_entry(&mut out, &change.old);
+            out.push_str(",\"new\":");
+            json_entry(&mut out, &change.new);
+            out.push('}');
+        }
+        out.push_str("]}");
+        writer.write_all(out.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for ArchiveDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.added {
+            writeln!(f, "A {}", entry.full_path().display())?;
+        }
+        for entry in &self.removed {
+            writeln!(f, "D {}", entry.full_path().display())?;
+        }
+        for change in &self.modified {
+            writeln!(
+                f,
+                "M {} ({} -> {} bytes)",
+                change.new.full_path().display(),
+                change.old.uncompressed_size(),
+                change.new.uncompressed_size()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn json_entry(out: &mut String, entry: &EntryInfo) {
+    out.push_str("{\"path\":");
+    json::string(out, &entry.full_path().to_string_lossy());
+    write!(
+        out,
+        ",\"crc32\":{},\"compressed_size\":{},\"uncompressed_size\":{}}}",
+        entry.crc32(),
+        entry.compressed_size(),
+        entry.uncompressed_size()
+    )
+    .unwrap();
+}
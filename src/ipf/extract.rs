@@ -0,0 +1,299 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufWriter, Read, Seek},
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use super::{header_to_entry, IpfArchive, ReadAt};
+use crate::{
+    entry::{IpfEntryHeader, ReadMode, Verifier},
+    error::{IpfError, Result},
+};
+
+/// Options of `IpfArchive::extract_all`
+pub struct ExtractOptions {
+    /// Number of threads, 0 for the available parallelism of the machine
+    pub threads: usize,
+    /// Stage of decoding to write entries at, see `IpfArchive::by_index_with`
+    ///
+    /// `ReadMode::Converted` (the default) writes ies files as csv under their `.ies` names,
+    /// use `ReadMode::Decompressed` to pack the files again, e.g. with `create_patch_from_dirs`
+    pub mode: ReadMode,
+    /// Whether to check crc32 and size of every entry
+    pub verify: bool,
+    /// Called after each entry with the number of entries done and the total number of entries
+    pub progress: Option<Box<dyn Fn(usize, usize) + Send + Sync>>,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            threads: 0,
+            mode: ReadMode::Converted,
+            verify: false,
+            progress: None,
+        }
+    }
+}
+
+/// Result of `IpfArchive::extract_all`
+#[derive(Debug)]
+pub struct ExtractReport {
+    extracted: usize,
+    errors: Vec<(usize, IpfError)>,
+}
+
+impl ExtractReport {
+    /// Number of entries extracted without an error
+    pub fn extracted(&self) -> usize {
+        self.extracted
+    }
+
+    /// Index of entry and its error, sorted by index
+    pub fn errors(&self) -> &[(usize, IpfError)] {
+        &self.errors
+    }
+
+    /// Whether every entry was extracted
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl IpfArchive<File> {
+    /// Extract every entry to `dest/archive_name/path` on many threads
+    ///
+    /// Each thread reads from the file with positional reads, so entries are read
+    /// at the same time. An error of an entry does not stop the others, and entries
+    /// with unsafe paths (see `IpfEntry::enclosed_path`) fail with `IpfError::UnsafePath`.
+    /// Of entries with the same path (ignoring case), the last one wins as with `extract_to`.
+    pub fn extract_all(&self, dest: impl AsRef<Path>, options: &ExtractOptions) -> ExtractReport {
+        let dest = dest.as_ref();
+        let threads = match options.threads {
+            0 => thread::available_parallelism().map_or(1, |threads| threads.get()),
+            threads => threads,
+        };
+
+        // entries of the same path (ignoring case, for Windows and macOS) are written
+        // one by one in index order by the same thread, so the last entry wins
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let mut paths = HashMap::new();
+        for (index, header) in self.entries.iter().enumerate() {
+            // unsafe paths fail on their own, without writing anything
+            let Some(path) = header.enclosed_path() else {
+                groups.push(vec![index]);
+                continue;
+            };
+            let group = *paths
+                .entry(path.to_string_lossy().to_lowercase())
+                .or_insert_with(|| {
+                    groups.push(Vec::new());
+                    groups.len() - 1
+                });
+            groups[group].push(index);
+        }
+        // in order of data, so the file is read mostly forward
+        groups.sort_by_key(|group| self.entries[group[0]].data_offset);
+
+        let next = AtomicUsize::new(0);
+        let done = AtomicUsize::new(0);
+        let errors = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            for _ in 0..threads.min(groups.len()) {
+                scope.spawn(|| {
+                    while let Some(group) = groups.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        for index in group {
+                            if let Err(err) = self.extract_entry(*index, dest, options) {
+                                errors.lock().unwrap().push((*index, err));
+                            }
+                            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                            if let Some(progress) = &options.progress {
+                                progress(done, self.len());
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut errors = errors.into_inner().unwrap();
+        errors.sort_by_key(|(index, _)| *index);
+        ExtractReport {
+            extracted: self.len() - errors.len(),
+            errors,
+        }
+    }
+
+    fn extract_entry(&self, index: usize, dest: &Path, options: &ExtractOptions) -> Result<()> {
+        let header = &self.entries[index];
+        let path = header
+            .enclosed_path()
+            .ok_or_else(|| unsafe_path(index, header))?;
+
+        let reader = PositionalReader {
+            file: &self.reader,
+            position: header.data_offset.into(),
+        };
+        let limit_reader = reader.take(header.compressed_size.into());
+        let verifier = options.verify.then(|| Verifier::new(index));
+        let mut entry = header_to_entry(
+            Cow::Borrowed(header),
+            limit_reader,
+            &self.options.crypto,
+            options.mode,
+            verifier,
+        )?;
+        write_file(&dest.join(path), &mut entry)
+    }
+}
+
+impl<R: Read + Seek> IpfArchive<R> {
+    /// Extract every entry to `dir/archive_name/path`, one by one
+    ///
+    /// Entries are read with `by_index`, so ies files are written as csv.
+    /// Fails before writing anything if any entry has an unsafe path,
+    /// see `IpfEntry::enclosed_path`
+    pub fn extract_to(&mut self, dir: impl AsRef<Path>) -> Result<()> {
+        let dir = dir.as_ref();
+        let paths = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, header)| {
+                header
+                    .enclosed_path()
+                    .ok_or_else(|| unsafe_path(index, header))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        for (index, path) in paths.into_iter().enumerate() {
+            let mut entry = self.by_index(index)?;
+            write_file(&dir.join(path), &mut entry)?;
+        }
+        Ok(())
+    }
+}
+
+fn unsafe_path(index: usize, header: &IpfEntryHeader) -> IpfError {
+    IpfError::UnsafePath {
+        index,
+        name: format!("{}/{}", header.archive_name, header.file_name),
+    }
+}
+
+fn write_file(path: &Path, mut reader: impl Read) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = BufWriter::new(File::create(path)?);
+    let written = io::copy(&mut reader, &mut file)
+        .map_err(unwrap_io_error)
+        .and_then(|_| Ok(file.into_inner().map_err(|err| err.into_error())?));
+    if written.is_err() {
+        // do not leave a broken file behind
+        let _ = fs::remove_file(path);
+    }
+    written.map(|_| ())
+}
+
+/// errors of verification come out of `Read` wrapped in `io::Error`
+fn unwrap_io_error(err: io::Error) -> IpfError {
+    if err.get_ref().is_some_and(|inner| inner.is::<IpfError>()) {
+        *err.into_inner().unwrap().downcast::<IpfError>().unwrap()
+    } else {
+        err.into()
+    }
+}
+
+/// Reads a file from `position` without moving the cursor of the file
+struct PositionalReader<'a> {
+    file: &'a File,
+    position: u64,
+}
+
+impl Read for PositionalReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.file.read_at(buf, self.position)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::{self, File},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use super::ExtractOptions;
+    use crate::{
+        error::IpfError,
+        ipf::{IpfArchive, IpfWriter},
+    };
+
+    #[test]
+    fn extract_all_reports_errors_in_order() {
+        let dir = std::env::temp_dir().join(format!("libtos-{}-extract-all", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("test.ipf");
+        let mut writer = IpfWriter::new(File::create(&path).unwrap());
+        let entries = [
+            ("xml.ipf", "a.xml", "first"),
+            ("xml.ipf", "../evil.xml", "evil"),
+            ("xml.ipf", "b.xml", "b"),
+            ("xml.ipf", "a.xml", "second"),
+            ("ui.ipf", "con.txt", "device"),
+            ("xml.ipf", "a.xml", "last"),
+        ];
+        for (archive_name, path, content) in entries {
+            writer.add_bytes(archive_name, path, content).unwrap();
+        }
+        writer.finish().unwrap();
+
+        // number of calls and the highest count of entries done
+        let progress = Arc::new((AtomicUsize::new(0), AtomicUsize::new(0)));
+        let archive = IpfArchive::open(&path).unwrap();
+        let counter = Arc::clone(&progress);
+        let options = ExtractOptions {
+            threads: 4,
+            progress: Some(Box::new(move |done, total| {
+                assert_eq!(total, 6);
+                counter.0.fetch_add(1, Ordering::Relaxed);
+                counter.1.fetch_max(done, Ordering::Relaxed);
+            })),
+            ..ExtractOptions::default()
+        };
+        let out = dir.join("out");
+        let report = archive.extract_all(&out, &options);
+
+        assert_eq!(report.extracted(), 4);
+        let errors: Vec<_> = report.errors().iter().map(|(index, _)| *index).collect();
+        assert_eq!(errors, [1, 4]);
+        assert!(report
+            .errors()
+            .iter()
+            .all(|(_, err)| matches!(err, IpfError::UnsafePath { .. })));
+        assert_eq!(progress.0.load(Ordering::Relaxed), 6);
+        assert_eq!(progress.1.load(Ordering::Relaxed), 6);
+
+        let read = |path: &str| fs::read_to_string(out.join(path)).unwrap();
+        assert_eq!(read("xml.ipf/a.xml"), "last");
+        assert_eq!(read("xml.ipf/b.xml"), "b");
+        assert!(!dir.join("evil.xml").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
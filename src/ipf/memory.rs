@@ -0,0 +1,63 @@
+use std::{
+    borrow::Cow,
+    io::{Cursor, Read},
+};
+
+use flate2::read::DeflateDecoder;
+
+use super::IpfArchive;
+use crate::{
+    crypto::IpfDecryptReader,
+    error::{IpfError, Result},
+};
+
+impl<T: AsRef<[u8]>> IpfArchive<Cursor<T>> {
+    /// Read an archive which is in memory, e.g. `Vec<u8>`, `&[u8]` or a memory map
+    ///
+    /// Use `IpfArchive::with_options(Cursor::new(bytes), options)` for other variants
+    pub fn from_bytes(bytes: T) -> Result<Self> {
+        IpfArchive::new(Cursor::new(bytes))
+    }
+
+    /// Data of an entry as it is stored in the archive, borrowed without copying
+    pub fn raw_bytes(&self, index: usize) -> Result<&[u8]> {
+        let header = self.entries.get(index).ok_or(IpfError::FileNotFound)?;
+        let start = header.data_offset as usize;
+        self.reader
+            .get_ref()
+            .as_ref()
+            .get(start..start + header.compressed_size as usize)
+            .ok_or(IpfError::InvalidArchive("Entry data is out of the archive"))
+    }
+
+    /// Decompressed data of an entry
+    ///
+    /// Entries stored as is (e.g. jpg, fsb, mp3) are borrowed without copying,
+    /// others are decrypted and decompressed from memory. Ies files are not converted.
+    pub fn bytes(&self, index: usize) -> Result<Cow<'_, [u8]>> {
+        let raw = self.raw_bytes(index)?;
+        let header = &self.entries[index];
+        if !header.is_compressed() {
+            return Ok(Cow::Borrowed(raw));
+        }
+
+        let mut buffer = Vec::with_capacity(header.uncompressed_size as usize);
+        let crypto = IpfDecryptReader::with_crypto(raw, header.crypto(&self.options.crypto));
+        DeflateDecoder::new(crypto).read_to_end(&mut buffer)?;
+        Ok(Cow::Owned(buffer))
+    }
+}
+
+#[cfg(feature = "memmap2")]
+impl IpfArchive<Cursor<memmap2::Mmap>> {
+    /// Open an archive as a memory map, see `from_bytes`
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the archive is alive,
+    /// see `memmap2::Mmap::map`
+    pub unsafe fn open_mmap(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        IpfArchive::from_bytes(memmap2::Mmap::map(&file)?)
+    }
+}
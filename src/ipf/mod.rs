@@ -2,16 +2,30 @@ use std::{
     borrow::Cow,
     convert::TryInto,
     fs::File,
-    io::{prelude::*, Seek, SeekFrom, Take},
+    io::{self, prelude::*, Seek, SeekFrom},
 };
 
 use crate::{
-    crypto::{IesReader, IpfCrypto},
-    entry::{IpfEntry, IpfEntryHeader, IpfEntryReader},
+    crypto::{CryptoProfile, IpfDecryptReader},
+    entry::{EntryInfo, IpfEntry, IpfEntryHeader, IpfEntryReader, ReadMode, Verifier},
     error::{IpfError, Result},
+    ies::IesReader,
 };
 
-use flate2::read::DeflateDecoder;
+use flate2::{read::DeflateDecoder, CrcReader};
+
+mod diff;
+mod extract;
+mod memory;
+mod shared;
+mod writer;
+
+pub use diff::{ArchiveDiff, EntryChange};
+pub use extract::{ExtractOptions, ExtractReport};
+pub use shared::{ReadAt, SharedIpfArchive, SharedIpfEntry, SharedReader};
+pub use writer::IpfWriter;
+
+pub(crate) const SIGNATURE: [u8; 4] = [0x50, 0x4B, 0x05, 0x06];
 
 #[derive(Clone)]
 pub(crate) struct IpfArchiveHeader {
@@ -24,7 +38,7 @@ pub(crate) struct IpfArchiveHeader {
 }
 
 impl IpfArchiveHeader {
-    fn parse(mut reader: (impl Read + Seek)) -> Result<Self> {
+    pub(crate) fn parse(mut reader: impl Read + Seek) -> Result<Self> {
         if reader.seek(SeekFrom::End(-24)).is_err() {
             return Err(IpfError::InvalidArchive(
                 "Failed to seek the reader to header (last 24 bytes)",
@@ -63,20 +77,39 @@ impl From<IpfArchiveHeader> for Vec<u8> {
     }
 }
 
+/// Options of reading and writing ipf archives
+#[derive(Clone, Debug, Default)]
+pub struct IpfOptions {
+    /// How entries are encrypted and compressed
+    pub crypto: CryptoProfile,
+    /// Whether to detect how each entry is stored from its data when reading,
+    /// rather than from the extension only. See `EntryInfo::method`
+    ///
+    /// Off by default, as this reads the first few bytes of every entry (all of an entry
+    /// whose start is ambiguous) when the archive is opened, and an entry which fails
+    /// to read fails to open the archive.
+    pub detect_method: bool,
+}
+
 #[derive(Clone)]
 pub struct IpfArchive<R> {
     reader: R,
-    #[allow(dead_code)]
-    header: IpfArchiveHeader,
-    entries: Vec<IpfEntryHeader>,
+    pub(crate) header: IpfArchiveHeader,
+    pub(crate) entries: Vec<IpfEntryHeader>,
+    pub(crate) options: IpfOptions,
 }
 
 impl<R: Read + Seek> IpfArchive<R> {
     /// Read and create a IpfArchive
-    pub fn new(mut reader: R) -> Result<IpfArchive<R>> {
+    pub fn new(reader: R) -> Result<IpfArchive<R>> {
+        Self::with_options(reader, IpfOptions::default())
+    }
+
+    /// Read and create a IpfArchive of another variant, e.g. with another `CryptoProfile`
+    pub fn with_options(mut reader: R, options: IpfOptions) -> Result<IpfArchive<R>> {
         let header = IpfArchiveHeader::parse(&mut reader)?;
 
-        if header.signature != [0x50, 0x4B, 0x05, 0x06] {
+        if header.signature != SIGNATURE {
             return Err(IpfError::InvalidArchive(
                 "Invalid magic signature. Not an IPF archive?",
             ));
@@ -87,14 +120,22 @@ impl<R: Read + Seek> IpfArchive<R> {
 
         // read local file tables
         for _ in 0..header.entry_count {
-            let data_table = IpfEntryHeader::parse(&mut reader)?;
+            let data_table = IpfEntryHeader::parse(&mut reader, &options.crypto)?;
             entries.push(data_table);
         }
 
+        if options.detect_method {
+            for header in &mut entries {
+                reader.seek(SeekFrom::Start(header.data_offset.into()))?;
+                header.detect_method(&mut reader, &options.crypto)?;
+            }
+        }
+
         Ok(Self {
             reader,
             header,
             entries,
+            options,
         })
     }
 
@@ -108,8 +149,53 @@ impl<R: Read + Seek> IpfArchive<R> {
         self.entries.is_empty()
     }
 
+    /// Revision of the game which this archive (patch) applies to
+    pub fn base_revision(&self) -> u32 {
+        self.header.base_revision
+    }
+
+    /// Revision of the game after this archive (patch) is applied
+    pub fn revision(&self) -> u32 {
+        self.header.revision
+    }
+
+    /// Iterate over metadata of every file in the archive
+    ///
+    /// Unlike `by_index`, this does not read any data of the entries,
+    /// which is read only when opening with `IpfOptions::detect_method`
+    pub fn entries(&self) -> impl ExactSizeIterator<Item = EntryInfo<'_>> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(index, header)| EntryInfo { index, header })
+    }
+
     /// Get a file entry by index
-    pub fn by_index(&mut self, index: usize) -> Result<IpfEntry> {
+    pub fn by_index(&mut self, index: usize) -> Result<IpfEntry<'_>> {
+        self.by_index_inner(index, ReadMode::Converted, None)
+    }
+
+    /// Get a file entry by index, read at the given stage of decoding
+    ///
+    /// `by_index` is the same as `ReadMode::Converted`
+    pub fn by_index_with(&mut self, index: usize, mode: ReadMode) -> Result<IpfEntry<'_>> {
+        self.by_index_inner(index, mode, None)
+    }
+
+    /// Get a file entry by index, which checks crc32 and size of the entry
+    ///
+    /// Reading the entry to the end fails with `IpfError::ChecksumMismatch`
+    /// or `IpfError::SizeMismatch` (wrapped in `std::io::Error`) if the data is corrupted
+    pub fn by_index_verified(&mut self, index: usize) -> Result<IpfEntry<'_>> {
+        self.by_index_inner(index, ReadMode::Converted, Some(Verifier::new(index)))
+    }
+
+    fn by_index_inner(
+        &mut self,
+        index: usize,
+        mode: ReadMode,
+        verifier: Option<Verifier>,
+    ) -> Result<IpfEntry<'_>> {
         if index >= self.len() {
             return Err(IpfError::FileNotFound);
         }
@@ -119,13 +205,73 @@ impl<R: Read + Seek> IpfArchive<R> {
             .seek(SeekFrom::Start(header.data_offset.into()))?;
         let limit_reader = (&mut self.reader as &mut dyn Read).take(header.compressed_size.into());
 
-        header_to_entry(header, limit_reader)
+        header_to_entry(
+            Cow::Borrowed(header),
+            limit_reader,
+            &self.options.crypto,
+            mode,
+            verifier,
+        )
+    }
+
+    /// Check crc32 and size of every file in the archive
+    ///
+    /// Result of each entry is at the index of the entry
+    pub fn verify_all(&mut self) -> Vec<Result<()>> {
+        (0..self.len()).map(|index| self.verify(index)).collect()
+    }
+
+    fn verify(&mut self, index: usize) -> Result<()> {
+        let header = &self.entries[index];
+
+        self.reader
+            .seek(SeekFrom::Start(header.data_offset.into()))?;
+        let limit_reader = (&mut self.reader as &mut dyn Read).take(header.compressed_size.into());
+        let mut raw = CrcReader::new(limit_reader);
+
+        let uncompressed = if header.is_compressed() {
+            let crypto =
+                IpfDecryptReader::with_crypto(&mut raw, header.crypto(&self.options.crypto));
+            let mut reader = DeflateDecoder::new(crypto);
+            io::copy(&mut reader, &mut io::sink())
+        } else {
+            io::copy(&mut raw, &mut io::sink())
+        };
+
+        let verifier = Verifier::new(index);
+        match uncompressed {
+            Ok(uncompressed) => verifier.verify(header, &mut raw, uncompressed),
+            Err(err) => {
+                // corrupted data fails to decompress, report it as a checksum mismatch
+                verifier.verify_stored(header, &mut raw)?;
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Recover keys of the archive from an entry of which a part is known,
+    /// see `CryptoProfile::recover_keys`
+    ///
+    /// Stride and stored extensions are kept from the options of the archive
+    pub fn recover_crypto(
+        &mut self,
+        index: usize,
+        plaintext: &[u8],
+        offset: usize,
+    ) -> Result<CryptoProfile> {
+        let mut encrypted = Vec::new();
+        self.by_index_with(index, ReadMode::Raw)?
+            .read_to_end(&mut encrypted)?;
+        self.options
+            .crypto
+            .clone()
+            .recover_keys(&encrypted, plaintext, offset)
     }
 
     /// Get a file entry by name
     ///
     /// use `by_index` if you know the index
-    pub fn by_name(&mut self, name: impl AsRef<std::path::Path>) -> Result<IpfEntry> {
+    pub fn by_name(&mut self, name: impl AsRef<std::path::Path>) -> Result<IpfEntry<'_>> {
         let name = name.as_ref().to_string_lossy();
         for (index, header) in self.entries.iter().enumerate() {
             if header.file_name().to_string_lossy() == name.as_ref() {
@@ -136,34 +282,57 @@ impl<R: Read + Seek> IpfArchive<R> {
     }
 }
 
-fn header_to_entry<'a>(
-    header: &'a IpfEntryHeader,
-    limit_reader: Take<&'a mut dyn Read>,
-) -> Result<IpfEntry<'a>> {
-    if header.worth_compress() {
-        let crypto = IpfCrypto::new(limit_reader);
-        // FIXME when is_some_and is stable
-        if header.extension().is_some() && header.extension().unwrap().to_lowercase() == "ies" {
+fn header_to_entry<'a, R: Read>(
+    header: Cow<'a, IpfEntryHeader>,
+    limit_reader: R,
+    profile: &CryptoProfile,
+    mode: ReadMode,
+    verifier: Option<Verifier>,
+) -> Result<IpfEntry<'a, R>> {
+    let raw = CrcReader::new(limit_reader);
+    if header.is_compressed() && mode != ReadMode::Raw {
+        let crypto = IpfDecryptReader::with_crypto(raw, header.crypto(profile));
+        if mode == ReadMode::Decrypted {
+            return Ok(IpfEntry {
+                reader: IpfEntryReader::Decrypted(crypto),
+                header,
+                verifier,
+            });
+        }
+
+        if mode == ReadMode::Converted && header.is_ies() {
             // TODO learn and make this better
             let mut reader = DeflateDecoder::new(crypto);
             let mut buffer = vec![];
-            reader.read_to_end(&mut buffer)?;
+            if let Err(err) = reader.read_to_end(&mut buffer) {
+                // corrupted data fails to decompress, report it as a checksum mismatch
+                if let Some(verifier) = &verifier {
+                    verifier.verify_stored(&header, reader.get_mut().get_mut())?;
+                }
+                return Err(err.into());
+            }
+            if let Some(verifier) = verifier {
+                verifier.verify(&header, reader.get_mut().get_mut(), buffer.len() as u64)?;
+            }
             let cursor = std::io::Cursor::new(buffer);
             return Ok(IpfEntry {
                 reader: IpfEntryReader::Ies(IesReader::new(cursor)),
-                header: Cow::Borrowed(header),
+                header,
+                verifier: None,
             });
         }
 
         return Ok(IpfEntry {
             reader: IpfEntryReader::Ipf(DeflateDecoder::new(crypto)),
-            header: Cow::Borrowed(header),
+            header,
+            verifier,
         });
     }
 
     Ok(IpfEntry {
-        reader: IpfEntryReader::Stored(limit_reader),
-        header: Cow::Borrowed(header),
+        reader: IpfEntryReader::Stored(raw),
+        header,
+        verifier,
     })
 }
 
@@ -173,3 +342,66 @@ impl IpfArchive<File> {
         IpfArchive::new(reader)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::{IpfArchive, IpfWriter};
+    use crate::{
+        error::IpfError,
+        ies::{CsvOptions, IesColumn, IesTable},
+    };
+
+    /// Archive of a lua and an ies file, with a byte in the middle of each entry flipped
+    fn corrupted() -> Vec<u8> {
+        let csv = "Level,Name\r\n".to_string() + &"3,Sword\r\n".repeat(50);
+        let columns = vec![IesColumn::new("Level", false), IesColumn::new("Name", true)];
+        let table =
+            IesTable::from_csv(csv.as_bytes(), "Item", columns, &CsvOptions::default()).unwrap();
+        let mut ies = Vec::new();
+        table.write_to(&mut ies).unwrap();
+
+        let mut writer = IpfWriter::new(Cursor::new(Vec::new()));
+        writer
+            .add_bytes("script.ipf", "f.lua", b"return 1\n".repeat(100))
+            .unwrap();
+        writer.add_bytes("ies.ipf", "item.ies", ies).unwrap();
+        let mut bytes = writer.finish().unwrap().into_inner();
+
+        let archive = IpfArchive::new(Cursor::new(&bytes)).unwrap();
+        let entries: Vec<_> = archive
+            .entries()
+            .map(|entry| (entry.data_offset() + entry.compressed_size() / 2) as usize)
+            .collect();
+        for middle in entries {
+            bytes[middle] ^= 0x55;
+        }
+        bytes
+    }
+
+    fn is_checksum_mismatch(err: &IpfError, expected_index: usize) -> bool {
+        matches!(err, IpfError::ChecksumMismatch { index, .. } if *index == expected_index)
+    }
+
+    #[test]
+    fn verified_read_of_corrupted_entry_is_checksum_mismatch() {
+        let mut archive = IpfArchive::new(Cursor::new(corrupted())).unwrap();
+
+        let err = archive
+            .by_index_verified(0)
+            .unwrap()
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        let err = err.into_inner().unwrap().downcast::<IpfError>().unwrap();
+        assert!(is_checksum_mismatch(&err, 0), "{err}");
+
+        // ies files are read as a whole when opened
+        let err = archive.by_index_verified(1).err().unwrap();
+        assert!(is_checksum_mismatch(&err, 1), "{err}");
+
+        let results = archive.verify_all();
+        assert!(is_checksum_mismatch(results[0].as_ref().unwrap_err(), 0));
+        assert!(is_checksum_mismatch(results[1].as_ref().unwrap_err(), 1));
+    }
+}
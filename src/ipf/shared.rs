@@ -0,0 +1,287 @@
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    sync::Arc,
+};
+
+use super::{header_to_entry, IpfArchive, IpfArchiveHeader, IpfOptions};
+use crate::{
+    entry::{EntryInfo, IpfEntry, IpfEntryHeader, ReadMode, Verifier},
+    error::{IpfError, Result},
+};
+
+/// Source of data which can be read at any position through a shared reference
+pub trait ReadAt {
+    /// Read bytes starting at `offset`, like `Read::read`
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+
+    /// Size of the whole data
+    fn size(&self) -> io::Result<u64>;
+}
+
+impl ReadAt for File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        #[cfg(unix)]
+        return std::os::unix::fs::FileExt::read_at(self, buf, offset);
+        #[cfg(windows)]
+        return std::os::windows::fs::FileExt::seek_read(self, buf, offset);
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+macro_rules! read_at_bytes {
+    ($($type:ty),*) => {$(
+        impl ReadAt for $type {
+            fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+                let bytes: &[u8] = self.as_ref();
+                let start = usize::try_from(offset).unwrap_or(usize::MAX).min(bytes.len());
+                let read = buf.len().min(bytes.len() - start);
+                buf[..read].copy_from_slice(&bytes[start..start + read]);
+                Ok(read)
+            }
+
+            fn size(&self) -> io::Result<u64> {
+                Ok(<$type as AsRef<[u8]>>::as_ref(self).len() as u64)
+            }
+        }
+    )*};
+}
+
+read_at_bytes!(Vec<u8>, Box<[u8]>, Arc<[u8]>, &'static [u8]);
+#[cfg(feature = "memmap2")]
+read_at_bytes!(memmap2::Mmap);
+
+/// Archive which can be read from many threads at once
+///
+/// Unlike `IpfArchive`, entries are opened through `&self` and own their reader,
+/// which reads `S` with positional reads. Cloning the archive is cheap.
+pub struct SharedIpfArchive<S> {
+    source: Arc<S>,
+    header: IpfArchiveHeader,
+    entries: Arc<[IpfEntryHeader]>,
+    options: Arc<IpfOptions>,
+}
+
+impl<S> Clone for SharedIpfArchive<S> {
+    fn clone(&self) -> Self {
+        Self {
+            source: Arc::clone(&self.source),
+            header: self.header.clone(),
+            entries: Arc::clone(&self.entries),
+            options: Arc::clone(&self.options),
+        }
+    }
+}
+
+/// Entry of `SharedIpfArchive`, which is `Send` and `'static` if `S` is `Send + Sync`
+pub type SharedIpfEntry<S> = IpfEntry<'static, SharedReader<S>>;
+
+/// Reads data of an entry from the source of `SharedIpfArchive`
+pub struct SharedReader<S> {
+    source: Arc<S>,
+    position: u64,
+    end: u64,
+}
+
+impl<S: ReadAt> Read for SharedReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = usize::try_from(self.end - self.position).unwrap_or(usize::MAX);
+        let length = buf.len().min(remaining);
+        let read = self.source.read_at(&mut buf[..length], self.position)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<S: ReadAt> SharedIpfArchive<S> {
+    /// Read and create a SharedIpfArchive
+    pub fn new(source: S) -> Result<Self> {
+        Self::with_options(source, IpfOptions::default())
+    }
+
+    /// Read and create a SharedIpfArchive of another variant, see `IpfArchive::with_options`
+    pub fn with_options(source: S, options: IpfOptions) -> Result<Self> {
+        let cursor = Cursor {
+            source: &source,
+            position: 0,
+        };
+        let archive = IpfArchive::with_options(cursor, options)?;
+        Ok(Self {
+            header: archive.header,
+            entries: archive.entries.into(),
+            options: Arc::new(archive.options),
+            source: Arc::new(source),
+        })
+    }
+
+    /// Number of files in the archive
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the archive has no files
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Revision of the game which this archive (patch) applies to
+    pub fn base_revision(&self) -> u32 {
+        self.header.base_revision
+    }
+
+    /// Revision of the game after this archive (patch) is applied
+    pub fn revision(&self) -> u32 {
+        self.header.revision
+    }
+
+    /// Iterate over metadata of every file in the archive, see `IpfArchive::entries`
+    pub fn entries(&self) -> impl ExactSizeIterator<Item = EntryInfo<'_>> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(index, header)| EntryInfo { index, header })
+    }
+
+    /// Get a file entry by index
+    pub fn by_index(&self, index: usize) -> Result<SharedIpfEntry<S>> {
+        self.by_index_inner(index, ReadMode::Converted, None)
+    }
+
+    /// Get a file entry by index, read at the given stage of decoding
+    pub fn by_index_with(&self, index: usize, mode: ReadMode) -> Result<SharedIpfEntry<S>> {
+        self.by_index_inner(index, mode, None)
+    }
+
+    /// Get a file entry by index, which checks crc32 and size of the entry,
+    /// see `IpfArchive::by_index_verified`
+    pub fn by_index_verified(&self, index: usize) -> Result<SharedIpfEntry<S>> {
+        self.by_index_inner(index, ReadMode::Converted, Some(Verifier::new(index)))
+    }
+
+    /// Get a file entry by name
+    pub fn by_name(&self, name: impl AsRef<Path>) -> Result<SharedIpfEntry<S>> {
+        let name = name.as_ref().to_string_lossy();
+        match self
+            .entries
+            .iter()
+            .position(|header| header.file_name().to_string_lossy() == name.as_ref())
+        {
+            Some(index) => self.by_index(index),
+            None => Err(IpfError::FileNotFound),
+        }
+    }
+
+    fn by_index_inner(
+        &self,
+        index: usize,
+        mode: ReadMode,
+        verifier: Option<Verifier>,
+    ) -> Result<SharedIpfEntry<S>> {
+        let header = self.entries.get(index).ok_or(IpfError::FileNotFound)?;
+        let reader = SharedReader {
+            source: Arc::clone(&self.source),
+            position: header.data_offset.into(),
+            end: u64::from(header.data_offset) + u64::from(header.compressed_size),
+        };
+        header_to_entry(
+            Cow::Owned(header.clone()),
+            reader,
+            &self.options.crypto,
+            mode,
+            verifier,
+        )
+    }
+}
+
+impl SharedIpfArchive<File> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(File::open(path)?)
+    }
+}
+
+/// Read + Seek over a `ReadAt`, to parse the file table
+struct Cursor<'a, S> {
+    source: &'a S,
+    position: u64,
+}
+
+impl<S: ReadAt> Read for Cursor<'_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.source.read_at(buf, self.position)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<S: ReadAt> Seek for Cursor<'_, S> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.source.size()?.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Read, thread};
+
+    use super::{SharedIpfArchive, SharedIpfEntry};
+    use crate::ipf::IpfWriter;
+
+    fn assert_sync<T: Send + Sync>() {}
+    fn assert_send<T: Send + 'static>() {}
+
+    #[test]
+    fn archive_is_sync_and_entries_are_send() {
+        assert_sync::<SharedIpfArchive<File>>();
+        assert_send::<SharedIpfEntry<File>>();
+        assert_sync::<SharedIpfArchive<Vec<u8>>>();
+        assert_send::<SharedIpfEntry<Vec<u8>>>();
+    }
+
+    #[test]
+    fn read_entries_on_other_threads() {
+        let mut writer = IpfWriter::new(std::io::Cursor::new(Vec::new()));
+        for index in 0..8 {
+            writer
+                .add_bytes(
+                    "test.ipf",
+                    format!("{index}.lua"),
+                    index.to_string().repeat(100),
+                )
+                .unwrap();
+        }
+        let archive = SharedIpfArchive::new(writer.finish().unwrap().into_inner()).unwrap();
+
+        let threads: Vec<_> = (0..archive.len())
+            .map(|index| {
+                let entry = archive.by_index_verified(index).unwrap();
+                thread::spawn(move || {
+                    let mut entry = entry;
+                    let mut content = String::new();
+                    entry.read_to_string(&mut content).unwrap();
+                    content
+                })
+            })
+            .collect();
+        for (index, thread) in threads.into_iter().enumerate() {
+            assert_eq!(thread.join().unwrap(), index.to_string().repeat(100));
+        }
+
+        // the archive itself is shared by reference
+        thread::scope(|scope| {
+            scope.spawn(|| assert!(archive.by_index(0).is_ok()));
+        });
+    }
+}
@@ -0,0 +1,224 @@
+use std::io::{self, prelude::*};
+
+use crate::{
+    crypto::IpfEncryptWriter,
+    entry::{IpfEntryHeader, ReadMode},
+    error::{IpfError, Result},
+};
+
+use flate2::{write::DeflateEncoder, Compression, CrcWriter};
+
+use super::{IpfArchive, IpfArchiveHeader, IpfOptions, SIGNATURE};
+
+/// Write a new ipf archive
+///
+/// Entries are deflated and encrypted the same way the game does,
+/// except for the extensions which are not worth to compress (e.g. jpg, fsb, mp3).
+/// Use `with_options` to write another variant.
+/// Call `finish` to write the file table, otherwise the archive is unreadable.
+pub struct IpfWriter<W: Write + Seek> {
+    writer: W,
+    entries: Vec<IpfEntryHeader>,
+    base_revision: u32,
+    revision: u32,
+    options: IpfOptions,
+}
+
+impl<W: Write + Seek> IpfWriter<W> {
+    /// Create a writer which starts writing at the current position of `writer`
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, IpfOptions::default())
+    }
+
+    /// Create a writer which encrypts and compresses entries as set in `options`
+    pub fn with_options(writer: W, options: IpfOptions) -> Self {
+        Self {
+            writer,
+            entries: Vec::new(),
+            base_revision: 0,
+            revision: 0,
+            options,
+        }
+    }
+
+    /// Set revision of the game which this archive (patch) applies to, 0 by default
+    pub fn set_base_revision(&mut self, base_revision: u32) {
+        self.base_revision = base_revision;
+    }
+
+    /// Set revision of the game after this archive (patch) is applied, 0 by default
+    pub fn set_revision(&mut self, revision: u32) {
+        self.revision = revision;
+    }
+
+    /// Add a file to the archive, reading its content from `reader`
+    ///
+    /// `archive_name` is e.g. example.ipf and `path` is e.g. event_banner/event1234.png
+    pub fn add_entry(
+        &mut self,
+        archive_name: impl Into<String>,
+        path: impl Into<String>,
+        mut reader: impl Read,
+    ) -> Result<()> {
+        self.check_entry_count()?;
+
+        let file_name = path.into();
+        let mut header = IpfEntryHeader {
+            method: self.options.crypto.method(&file_name),
+            file_name,
+            archive_name: archive_name.into(),
+            crc32: 0,
+            compressed_size: 0,
+            uncompressed_size: 0,
+            data_offset: to_u32(self.writer.stream_position()?)?,
+        };
+        if header.file_name.len() > u16::MAX.into() || header.archive_name.len() > u16::MAX.into() {
+            return Err(IpfError::InvalidArchive(
+                "Entry name is longer than 65535 bytes",
+            ));
+        }
+
+        // crc32 covers the bytes as they are stored in the archive
+        let mut crc_writer = CrcWriter::new(&mut self.writer);
+        let uncompressed_size = if header.is_compressed() {
+            let profile = header.crypto(&self.options.crypto);
+            let crypto = IpfEncryptWriter::with_crypto(&mut crc_writer, profile);
+            let mut encoder = DeflateEncoder::new(crypto, Compression::default());
+            let size = io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?;
+            size
+        } else {
+            io::copy(&mut reader, &mut crc_writer)?
+        };
+
+        header.crc32 = crc_writer.crc().sum();
+        header.compressed_size = to_u32(self.writer.stream_position()?)? - header.data_offset;
+        header.uncompressed_size = to_u32(uncompressed_size)?;
+        self.entries.push(header);
+        Ok(())
+    }
+
+    /// Add a file to the archive from memory
+    pub fn add_bytes(
+        &mut self,
+        archive_name: impl Into<String>,
+        path: impl Into<String>,
+        bytes: impl AsRef<[u8]>,
+    ) -> Result<()> {
+        self.add_entry(archive_name, path, bytes.as_ref())
+    }
+
+    /// Copy an entry of another archive as it is stored, without decompressing it
+    ///
+    /// The entry stays encrypted as in `archive`, even if the options of this writer differ
+    pub fn copy_entry<R: Read + Seek>(
+        &mut self,
+        archive: &mut IpfArchive<R>,
+        index: usize,
+    ) -> Result<()> {
+        self.check_entry_count()?;
+
+        let data_offset = to_u32(self.writer.stream_position()?)?;
+        let mut entry = archive.by_index_with(index, ReadMode::Raw)?;
+        let copied = io::copy(&mut entry, &mut self.writer)?;
+
+        let mut header = entry.header.into_owned();
+        if copied != header.compressed_size.into() {
+            return Err(IpfError::SizeMismatch {
+                index,
+                name: header.full_path().to_string_lossy().into_owned(),
+                expected: header.compressed_size.into(),
+                actual: copied,
+            });
+        }
+        header.data_offset = data_offset;
+        self.entries.push(header);
+        Ok(())
+    }
+
+    fn check_entry_count(&self) -> Result<()> {
+        if self.entries.len() >= u16::MAX.into() {
+            return Err(IpfError::InvalidArchive(
+                "Too many entries, ipf archive can hold up to 65535 files",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Write the file table and the footer, then return the inner writer
+    pub fn finish(mut self) -> Result<W> {
+        let local_file_offset = to_u32(self.writer.stream_position()?)?;
+        let entry_count = self.entries.len() as u16;
+        for header in self.entries.drain(..) {
+            self.writer.write_all(&header.into_bytes())?;
+        }
+        let header_offset = to_u32(self.writer.stream_position()?)?;
+
+        let header = IpfArchiveHeader {
+            entry_count,
+            local_file_offset,
+            header_offset,
+            signature: SIGNATURE,
+            base_revision: self.base_revision,
+            revision: self.revision,
+        };
+        self.writer.write_all(&header.into_bytes())?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn to_u32(value: u64) -> Result<u32> {
+    value
+        .try_into()
+        .map_err(|_| IpfError::InvalidArchive("Ipf archive can not be larger than 4 GiB"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::IpfWriter;
+    use crate::ipf::IpfArchive;
+
+    #[test]
+    fn written_archive_opens_again() {
+        let lua = b"function f() return 1 end\n".repeat(100);
+        let jpg = b"\xFF\xD8\xFF\xE0 not really a jpg";
+
+        let mut writer = IpfWriter::new(Cursor::new(Vec::new()));
+        writer.set_base_revision(10);
+        writer.set_revision(11);
+        writer.add_bytes("script.ipf", "lib/f.lua", &lua).unwrap();
+        writer.add_bytes("ui.ipf", "banner/event.jpg", jpg).unwrap();
+        writer.add_bytes("ui.ipf", "empty.xml", []).unwrap();
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let mut archive = IpfArchive::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.len(), 3);
+        assert_eq!(archive.base_revision(), 10);
+        assert_eq!(archive.revision(), 11);
+
+        let entries: Vec<_> = archive.entries().collect();
+        assert!(entries[0].is_encrypted());
+        assert!(entries[0].compressed_size() < entries[0].uncompressed_size());
+        assert!(entries[1].is_stored());
+        assert_eq!(entries[1].compressed_size(), jpg.len() as u32);
+        assert_eq!(entries[2].uncompressed_size(), 0);
+        assert_eq!(
+            entries[1].full_path(),
+            std::path::Path::new("ui.ipf/banner/event.jpg")
+        );
+
+        for (index, expected) in [&lua[..], jpg, b""].into_iter().enumerate() {
+            let mut data = Vec::new();
+            archive
+                .by_index_verified(index)
+                .unwrap()
+                .read_to_end(&mut data)
+                .unwrap();
+            assert_eq!(data, expected);
+        }
+        assert!(archive.verify_all().iter().all(Result::is_ok));
+    }
+}
@@ -0,0 +1,29 @@
+//! Minimal json writing for exports and reports
+
+use std::fmt::Write;
+
+/// Append `value` as a json string
+pub(crate) fn string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if u32::from(c) < 0x20 => write!(out, "\\u{:04x}", u32::from(c)).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Append `value` as a json number, or null if it is not finite
+pub(crate) fn number(out: &mut String, value: f32) {
+    if value.is_finite() {
+        write!(out, "{value}").unwrap();
+    } else {
+        out.push_str("null");
+    }
+}
@@ -1,7 +1,17 @@
 mod crypto;
 mod entry;
 mod error;
+pub mod ies;
 mod ipf;
+mod json;
+mod overlay;
+pub mod patch;
 
+pub use crypto::{CryptoProfile, IpfDecryptReader, IpfEncryptWriter};
+pub use entry::{EntryInfo, IpfEntry, Method, ReadMode};
 pub use error::{IpfError, Result};
-pub use ipf::IpfArchive;
+pub use ipf::{
+    ArchiveDiff, EntryChange, ExtractOptions, ExtractReport, IpfArchive, IpfOptions, IpfWriter,
+    ReadAt, SharedIpfArchive, SharedIpfEntry, SharedReader,
+};
+pub use overlay::IpfOverlay;
@@ -0,0 +1,221 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Read, Seek},
+    path::Path,
+};
+
+use crate::{
+    entry::{normalize_path, EntryInfo, IpfEntry},
+    error::{IpfError, Result},
+    ipf::IpfArchive,
+};
+
+/// Merged view of many ipf archives, like the game sees its `data` and `patch` directories
+///
+/// Files are looked up by `archive_name/path`, e.g. xml.ipf/item.xml.
+/// When several archives have the same file, the one of the archive with
+/// the highest revision wins, or the one added last if revisions are equal.
+/// Paths are compared case-insensitively with `/` and `\` as separators, like on Windows.
+pub struct IpfOverlay<R> {
+    archives: Vec<OverlayArchive<R>>,
+    /// normalized full path -> (archive, entry index)
+    files: BTreeMap<String, (usize, usize)>,
+}
+
+struct OverlayArchive<R> {
+    name: String,
+    archive: IpfArchive<R>,
+}
+
+impl<R: Read + Seek> IpfOverlay<R> {
+    /// Create an empty overlay
+    pub fn new() -> Self {
+        Self {
+            archives: Vec::new(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Add an archive to the overlay, `name` is used to tell where a file comes from,
+    /// e.g. the file name of the archive
+    pub fn add(&mut self, name: impl Into<String>, archive: IpfArchive<R>) {
+        let in_order = self
+            .archives
+            .last()
+            .is_none_or(|last| last.archive.header.revision <= archive.header.revision);
+        self.archives.push(OverlayArchive {
+            name: name.into(),
+            archive,
+        });
+
+        if in_order {
+            self.insert_files(self.archives.len() - 1);
+        } else {
+            // stable, so archives of the same revision keep the order they were added in
+            self.archives
+                .sort_by_key(|archive| archive.archive.header.revision);
+            self.files.clear();
+            for archive in 0..self.archives.len() {
+                self.insert_files(archive);
+            }
+        }
+    }
+
+    /// Number of files in the merged view
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Whether the merged view has no files
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Names of the archives from the lowest revision to the highest
+    pub fn archives(&self) -> impl ExactSizeIterator<Item = &str> {
+        self.archives.iter().map(|archive| archive.name.as_str())
+    }
+
+    /// Iterate over metadata of the winning entry of every file, sorted by path
+    ///
+    /// `EntryInfo::index` is the index in the archive named by `source`
+    pub fn entries(&self) -> impl ExactSizeIterator<Item = EntryInfo<'_>> {
+        self.files.values().map(|(archive, index)| EntryInfo {
+            index: *index,
+            header: &self.archives[*archive].archive.entries[*index],
+        })
+    }
+
+    /// Whether the merged view has the file
+    pub fn contains(&self, path: impl AsRef<Path>) -> bool {
+        self.find(path.as_ref()).is_some()
+    }
+
+    /// Name of the archive which supplies the file
+    pub fn source(&self, path: impl AsRef<Path>) -> Option<&str> {
+        self.find(path.as_ref())
+            .map(|(archive, _)| self.archives[archive].name.as_str())
+    }
+
+    /// Metadata of the winning entry of the file
+    pub fn info(&self, path: impl AsRef<Path>) -> Option<EntryInfo<'_>> {
+        self.find(path.as_ref()).map(|(archive, index)| EntryInfo {
+            index,
+            header: &self.archives[archive].archive.entries[index],
+        })
+    }
+
+    /// Open the winning entry of the file
+    ///
+    /// `path` is e.g. xml.ipf/item.xml, or xml/item.xml without the extension of archive
+    pub fn open(&mut self, path: impl AsRef<Path>) -> Result<IpfEntry<'_>> {
+        let (archive, index) = self.find(path.as_ref()).ok_or(IpfError::FileNotFound)?;
+        self.archives[archive].archive.by_index(index)
+    }
+
+    fn find(&self, path: &Path) -> Option<(usize, usize)> {
+        let path = normalize_path(&path.to_string_lossy());
+        if let Some(found) = self.files.get(&path) {
+            return Some(*found);
+        }
+        // archive name without .ipf
+        let (archive_name, rest) = path.split_once('/')?;
+        self.files
+            .get(&format!("{archive_name}.ipf/{rest}"))
+            .copied()
+    }
+
+    fn insert_files(&mut self, archive: usize) {
+        for (index, header) in self.archives[archive].archive.entries.iter().enumerate() {
+            self.files.insert(header.key(), (archive, index));
+        }
+    }
+}
+
+impl<R: Read + Seek> Default for IpfOverlay<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IpfOverlay<File> {
+    /// Open every archive of `paths`, named after their file names
+    pub fn open_all(paths: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<Self> {
+        let mut overlay = Self::new();
+        for path in paths {
+            let path = path.as_ref();
+            let name = path.file_name().unwrap_or(path.as_os_str());
+            overlay.add(name.to_string_lossy(), IpfArchive::open(path)?);
+        }
+        Ok(overlay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::IpfOverlay;
+    use crate::ipf::{IpfArchive, IpfWriter};
+
+    fn archive(revision: u32, files: &[(&str, &str)]) -> IpfArchive<Cursor<Vec<u8>>> {
+        let mut writer = IpfWriter::new(Cursor::new(Vec::new()));
+        writer.set_revision(revision);
+        for (path, content) in files {
+            let (archive_name, path) = path.split_once('/').unwrap();
+            writer.add_bytes(archive_name, path, content).unwrap();
+        }
+        IpfArchive::new(Cursor::new(writer.finish().unwrap().into_inner())).unwrap()
+    }
+
+    fn read(overlay: &mut IpfOverlay<Cursor<Vec<u8>>>, path: &str) -> String {
+        let mut content = String::new();
+        overlay
+            .open(path)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn highest_revision_wins() {
+        let mut overlay = IpfOverlay::new();
+        overlay.add("102.ipf", archive(102, &[("xml.ipf/item.xml", "102")]));
+        overlay.add(
+            "data.ipf",
+            archive(0, &[("xml.ipf/item.xml", "0"), ("xml.ipf/skill.xml", "0")]),
+        );
+        overlay.add("101.ipf", archive(101, &[("XML.ipf/Item.xml", "101")]));
+
+        let archives: Vec<_> = overlay.archives().collect();
+        assert_eq!(archives, ["data.ipf", "101.ipf", "102.ipf"]);
+        assert_eq!(overlay.len(), 2);
+        assert_eq!(overlay.source("xml.ipf/item.xml"), Some("102.ipf"));
+        assert_eq!(overlay.source("xml.ipf/skill.xml"), Some("data.ipf"));
+        assert_eq!(read(&mut overlay, "xml.ipf/item.xml"), "102");
+    }
+
+    #[test]
+    fn last_added_wins_at_same_revision() {
+        let mut overlay = IpfOverlay::new();
+        overlay.add("b.ipf", archive(5, &[("xml.ipf/item.xml", "b")]));
+        overlay.add("old.ipf", archive(1, &[("xml.ipf/item.xml", "old")]));
+        overlay.add("c.ipf", archive(5, &[("xml.ipf/item.xml", "c")]));
+        assert_eq!(overlay.source("xml.ipf/item.xml"), Some("c.ipf"));
+        assert_eq!(read(&mut overlay, "xml.ipf/item.xml"), "c");
+    }
+
+    #[test]
+    fn find_without_extension_of_archive() {
+        let mut overlay = IpfOverlay::new();
+        overlay.add("data.ipf", archive(0, &[("xml.ipf/item.xml", "item")]));
+        assert!(overlay.contains("XML\\item.XML"));
+        assert_eq!(overlay.source("xml/item.xml"), Some("data.ipf"));
+        assert_eq!(read(&mut overlay, "xml/item.xml"), "item");
+        assert!(!overlay.contains("xml/skill.xml"));
+        assert_eq!(overlay.source("item.xml"), None);
+        assert!(overlay.open("item.xml").is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Read, Seek, Write},
+};
+
+use crate::{
+    error::Result,
+    ipf::{IpfArchive, IpfWriter},
+};
+
+/// Apply `patches` in the given order onto `base`, and write the merged archive to `writer`
+///
+/// Entries of `base` which a patch also has (same `archive_name/path`) are replaced
+/// by the entry of the last such patch. Entries which only patches have are added
+/// after them if they belong to one of the archive names in `base`, since a patch
+/// usually holds files of many archives. Every entry is copied as it is stored,
+/// without decompressing it.
+///
+/// The merged archive keeps `base_revision` of `base` and takes `revision` of the last patch.
+/// Use `PatchChain` to put patches in order.
+pub fn apply_patches<R, P, W>(
+    base: &mut IpfArchive<R>,
+    patches: &mut [IpfArchive<P>],
+    writer: W,
+) -> Result<W>
+where
+    R: Read + Seek,
+    P: Read + Seek,
+    W: Write + Seek,
+{
+    let archive_names: HashSet<String> = base
+        .entries
+        .iter()
+        .map(|header| header.archive_name.to_ascii_lowercase())
+        .collect();
+
+    // (None for base or index of patch, index of entry) in the order they are written
+    let mut sources: Vec<(Option<usize>, usize)> = Vec::with_capacity(base.len());
+    let mut positions = HashMap::new();
+    for (index, header) in base.entries.iter().enumerate() {
+        positions.insert(header.key(), sources.len());
+        sources.push((None, index));
+    }
+    for (patch, archive) in patches.iter().enumerate() {
+        for (index, header) in archive.entries.iter().enumerate() {
+            let key = header.key();
+            match positions.get(&key) {
+                Some(position) => sources[*position] = (Some(patch), index),
+                None if archive_names.contains(&header.archive_name.to_ascii_lowercase()) => {
+                    positions.insert(key, sources.len());
+                    sources.push((Some(patch), index));
+                }
+                None => {}
+            }
+        }
+    }
+
+    let mut writer = IpfWriter::new(writer);
+    writer.set_base_revision(base.base_revision());
+    writer.set_revision(
+        patches
+            .last()
+            .map_or(base.revision(), |patch| patch.revision()),
+    );
+    for (source, index) in sources {
+        match source {
+            None => writer.copy_entry(base, index)?,
+            Some(patch) => writer.copy_entry(&mut patches[patch], index)?,
+        }
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::apply_patches;
+    use crate::ipf::{IpfArchive, IpfWriter};
+
+    fn archive(revisions: (u32, u32), files: &[(&str, &str)]) -> IpfArchive<Cursor<Vec<u8>>> {
+        let mut writer = IpfWriter::new(Cursor::new(Vec::new()));
+        writer.set_base_revision(revisions.0);
+        writer.set_revision(revisions.1);
+        for (path, content) in files {
+            let (archive_name, path) = path.split_once('/').unwrap();
+            writer.add_bytes(archive_name, path, content).unwrap();
+        }
+        IpfArchive::new(Cursor::new(writer.finish().unwrap().into_inner())).unwrap()
+    }
+
+    #[test]
+    fn patches_replace_and_add_entries() {
+        let mut base = archive(
+            (0, 100),
+            &[("xml.ipf/item.xml", "base"), ("xml.ipf/skill.xml", "base")],
+        );
+        let mut patches = vec![
+            archive(
+                (100, 101),
+                &[
+                    ("xml.ipf/item.xml", "101"),
+                    ("XML.ipf/Skill.xml", "101"),
+                    ("xml.ipf/new.xml", "101"),
+                    ("ui.ipf/banner.lua", "101"),
+                ],
+            ),
+            archive((101, 102), &[("xml.ipf/item.xml", "102")]),
+        ];
+
+        let merged = apply_patches(&mut base, &mut patches, Cursor::new(Vec::new())).unwrap();
+        let mut merged = IpfArchive::new(Cursor::new(merged.into_inner())).unwrap();
+        assert_eq!((merged.base_revision(), merged.revision()), (0, 102));
+
+        let files: Vec<_> = (0..merged.len())
+            .map(|index| {
+                let mut entry = merged.by_index(index).unwrap();
+                let mut content = String::new();
+                entry.read_to_string(&mut content).unwrap();
+                (
+                    entry.full_path().to_string_lossy().replace('\\', "/"),
+                    content,
+                )
+            })
+            .collect();
+        // ui.ipf is not in the base, so its entries are dropped
+        assert_eq!(
+            files,
+            [
+                ("xml.ipf/item.xml".into(), "102".into()),
+                ("XML.ipf/Skill.xml".into(), "101".into()),
+                ("xml.ipf/new.xml".into(), "101".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_patches_keep_base() {
+        let mut base = archive((5, 6), &[("xml.ipf/item.xml", "base")]);
+        let merged =
+            apply_patches::<_, Cursor<Vec<u8>>, _>(&mut base, &mut [], Cursor::new(Vec::new()))
+                .unwrap();
+        let merged = IpfArchive::new(Cursor::new(merged.into_inner())).unwrap();
+        assert_eq!(
+            (merged.base_revision(), merged.revision(), merged.len()),
+            (5, 6, 1)
+        );
+    }
+}
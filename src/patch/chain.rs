@@ -0,0 +1,403 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    fs::File,
+    io::{Read, Seek},
+    path::Path,
+};
+
+use crate::{
+    error::{IpfError, Result},
+    ipf::{IpfArchive, IpfArchiveHeader, SIGNATURE},
+};
+
+/// Revisions of a patch archive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatchInfo {
+    /// Name of the patch, e.g. its file name
+    pub name: String,
+    /// Revision which the patch applies to
+    pub base_revision: u32,
+    /// Revision after the patch is applied
+    pub revision: u32,
+}
+
+impl PatchInfo {
+    pub fn new(name: impl Into<String>, base_revision: u32, revision: u32) -> Self {
+        Self {
+            name: name.into(),
+            base_revision,
+            revision,
+        }
+    }
+
+    /// Take revisions of an opened archive
+    pub fn from_archive<R: Read + Seek>(name: impl Into<String>, archive: &IpfArchive<R>) -> Self {
+        Self::new(name, archive.base_revision(), archive.revision())
+    }
+
+    /// Read revisions from the footer of an archive, without reading its file table
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let header = IpfArchiveHeader::parse(File::open(path)?)?;
+        if header.signature != SIGNATURE {
+            return Err(IpfError::InvalidArchive(
+                "Invalid magic signature. Not an IPF archive?",
+            ));
+        }
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        Ok(Self::new(
+            name.to_string_lossy(),
+            header.base_revision,
+            header.revision,
+        ))
+    }
+}
+
+/// Problem found while chaining patches
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChainIssue {
+    /// No patch goes from revision `from`, the chain continues at `to`
+    /// (or ends before reaching `to`)
+    Gap { from: u32, to: u32 },
+    /// More than one patch applies to `base_revision`, the first one is in the chain.
+    /// It is the one which goes the furthest without a gap
+    Fork {
+        base_revision: u32,
+        names: Vec<String>,
+    },
+    /// Patches of the same revisions, the first one is in the chain
+    Duplicate {
+        base_revision: u32,
+        revision: u32,
+        names: Vec<String>,
+    },
+    /// Patch which is not in the chain for another reason,
+    /// e.g. it applies to a revision before the start of the chain
+    Unused { name: String },
+}
+
+impl fmt::Display for ChainIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChainIssue::Gap { from, to } => write!(f, "missing patches from {from} to {to}"),
+            ChainIssue::Fork {
+                base_revision,
+                names,
+            } => write!(
+                f,
+                "patches {} all apply to {base_revision}",
+                names.join(", ")
+            ),
+            ChainIssue::Duplicate {
+                base_revision,
+                revision,
+                names,
+            } => write!(
+                f,
+                "patches {} are all from {base_revision} to {revision}",
+                names.join(", ")
+            ),
+            ChainIssue::Unused { name } => write!(f, "patch {name} is not in the chain"),
+        }
+    }
+}
+
+/// Patches sorted into a chain, in which `base_revision` of each patch
+/// is `revision` of the previous one
+#[derive(Clone, Debug)]
+pub struct PatchChain {
+    patches: Vec<PatchInfo>,
+    issues: Vec<ChainIssue>,
+}
+
+impl PatchChain {
+    /// Chain patches starting at the lowest base revision among them
+    pub fn new(patches: impl IntoIterator<Item = PatchInfo>) -> Self {
+        Self::build(None, None, patches.into_iter().collect())
+    }
+
+    /// Chain patches from revision `start` to `end`, e.g. from the installed revision
+    /// to the latest one. Missing patches at either end are reported as gaps.
+    pub fn between(start: u32, end: u32, patches: impl IntoIterator<Item = PatchInfo>) -> Self {
+        Self::build(Some(start), Some(end), patches.into_iter().collect())
+    }
+
+    /// Chain patch archives of `paths`, see `PatchInfo::open`
+    pub fn open_all(paths: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<Self> {
+        let patches = paths
+            .into_iter()
+            .map(PatchInfo::open)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::new(patches))
+    }
+
+    fn build(start: Option<u32>, end: Option<u32>, patches: Vec<PatchInfo>) -> Self {
+        let mut issues = Vec::new();
+
+        // group by revisions, keeping the order patches are given in
+        let mut unique: BTreeMap<(u32, u32), Vec<PatchInfo>> = BTreeMap::new();
+        for patch in patches {
+            unique
+                .entry((patch.base_revision, patch.revision))
+                .or_default()
+                .push(patch);
+        }
+        for ((base_revision, revision), same) in &unique {
+            if same.len() > 1 {
+                issues.push(ChainIssue::Duplicate {
+                    base_revision: *base_revision,
+                    revision: *revision,
+                    names: same.iter().map(|patch| patch.name.clone()).collect(),
+                });
+            }
+        }
+
+        // highest revision reachable from each revision without a gap,
+        // going back from the last patch so later revisions are done first
+        let mut reach: HashMap<u32, u32> = HashMap::new();
+        for (base, revision) in unique.keys().rev() {
+            if revision > base {
+                let further = reach.get(revision).copied().unwrap_or(*revision);
+                let reached = reach.entry(*base).or_insert(*base);
+                *reached = (*reached).max(further);
+            }
+        }
+        let reach_of = |revision: u32| {
+            let reached = reach.get(&revision).copied().unwrap_or(revision);
+            end.map_or(reached, |end| reached.min(end))
+        };
+
+        let mut chain: Vec<PatchInfo> = Vec::new();
+        let mut used = HashSet::new();
+        let mut forked = HashSet::new();
+        let lowest = unique
+            .keys()
+            .filter(|(base, revision)| revision > base)
+            .map(|(base, _)| *base)
+            .min();
+        let mut current = match start.or(lowest) {
+            Some(current) => current,
+            None => {
+                return Self {
+                    patches: chain,
+                    issues,
+                }
+            }
+        };
+        loop {
+            if end.is_some_and(|end| current >= end) {
+                break;
+            }
+            // patches going backward or nowhere can not be chained
+            let mut candidates: Vec<_> = unique
+                .range((current, 0)..=(current, u32::MAX))
+                .map(|(key, _)| *key)
+                .filter(|(base, revision)| revision > base)
+                .filter(|key| !used.contains(key))
+                .collect();
+            // at a fork, take the patch which goes the furthest without a gap,
+            // the one to the lowest revision of those
+            if let Some(position) = candidates
+                .iter()
+                .enumerate()
+                .max_by_key(|(position, (_, revision))| {
+                    (reach_of(*revision), std::cmp::Reverse(*position))
+                })
+                .map(|(position, _)| position)
+            {
+                let next = candidates.remove(position);
+                let others = candidates;
+                if !others.is_empty() {
+                    issues.push(ChainIssue::Fork {
+                        base_revision: current,
+                        names: std::iter::once(&next)
+                            .chain(&others)
+                            .map(|key| unique[key][0].name.clone())
+                            .collect(),
+                    });
+                    forked.extend(others);
+                }
+                used.insert(next);
+                chain.push(unique[&next][0].clone());
+                current = next.1;
+                continue;
+            }
+
+            let next_base = unique
+                .keys()
+                .filter(|(base, revision)| *base > current && revision > base)
+                .map(|(base, _)| *base)
+                .min();
+            match next_base {
+                Some(base) if end.is_none_or(|end| base < end) => {
+                    issues.push(ChainIssue::Gap {
+                        from: current,
+                        to: base,
+                    });
+                    current = base;
+                }
+                _ => break,
+            }
+        }
+        if let Some(end) = end {
+            if current < end {
+                issues.push(ChainIssue::Gap {
+                    from: current,
+                    to: end,
+                });
+            }
+        }
+
+        for key in unique.keys() {
+            if !used.contains(key) && !forked.contains(key) {
+                issues.push(ChainIssue::Unused {
+                    name: unique[key][0].name.clone(),
+                });
+            }
+        }
+
+        Self {
+            patches: chain,
+            issues,
+        }
+    }
+
+    /// Patches in the order they are applied
+    pub fn patches(&self) -> &[PatchInfo] {
+        &self.patches
+    }
+
+    /// Gaps, forks, duplicates and unused patches found while chaining
+    pub fn issues(&self) -> &[ChainIssue] {
+        &self.issues
+    }
+
+    /// Whether every patch is in the chain without any gap
+    pub fn is_complete(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Revision which the first patch applies to
+    pub fn base_revision(&self) -> Option<u32> {
+        self.patches.first().map(|patch| patch.base_revision)
+    }
+
+    /// Revision after the last patch is applied
+    pub fn revision(&self) -> Option<u32> {
+        self.patches.last().map(|patch| patch.revision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChainIssue, PatchChain, PatchInfo};
+
+    fn patches(revisions: &[(&str, u32, u32)]) -> Vec<PatchInfo> {
+        revisions
+            .iter()
+            .map(|(name, base, revision)| PatchInfo::new(*name, *base, *revision))
+            .collect()
+    }
+
+    fn names(chain: &PatchChain) -> Vec<&str> {
+        chain
+            .patches()
+            .iter()
+            .map(|patch| patch.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn sorts_patches_into_chain() {
+        let chain = PatchChain::new(patches(&[("c", 3, 4), ("a", 1, 2), ("b", 2, 3)]));
+        assert_eq!(names(&chain), ["a", "b", "c"]);
+        assert!(chain.is_complete());
+        assert_eq!(
+            (chain.base_revision(), chain.revision()),
+            (Some(1), Some(4))
+        );
+    }
+
+    #[test]
+    fn gap() {
+        let chain = PatchChain::new(patches(&[("a", 1, 2), ("b", 3, 4)]));
+        assert_eq!(names(&chain), ["a", "b"]);
+        assert_eq!(chain.issues(), [ChainIssue::Gap { from: 2, to: 3 }]);
+    }
+
+    #[test]
+    fn fork_takes_branch_which_continues() {
+        let chain = PatchChain::new(patches(&[
+            ("a", 1, 2),
+            ("b", 2, 3),
+            ("c", 2, 4),
+            ("d", 4, 5),
+        ]));
+        assert_eq!(names(&chain), ["a", "c", "d"]);
+        assert_eq!(
+            chain.issues(),
+            [ChainIssue::Fork {
+                base_revision: 2,
+                names: vec!["c".into(), "b".into()],
+            }]
+        );
+    }
+
+    #[test]
+    fn fork_of_same_reach_takes_lowest_revision() {
+        let chain = PatchChain::new(patches(&[("a", 1, 3), ("b", 1, 2), ("c", 2, 3)]));
+        assert_eq!(names(&chain), ["b", "c"]);
+        assert_eq!(
+            chain.issues(),
+            [ChainIssue::Fork {
+                base_revision: 1,
+                names: vec!["b".into(), "a".into()],
+            }]
+        );
+    }
+
+    #[test]
+    fn duplicate() {
+        let chain = PatchChain::new(patches(&[("a", 1, 2), ("a2", 1, 2), ("b", 2, 3)]));
+        assert_eq!(names(&chain), ["a", "b"]);
+        assert_eq!(
+            chain.issues(),
+            [ChainIssue::Duplicate {
+                base_revision: 1,
+                revision: 2,
+                names: vec!["a".into(), "a2".into()],
+            }]
+        );
+    }
+
+    #[test]
+    fn unused() {
+        let chain = PatchChain::between(2, 3, patches(&[("a", 1, 2), ("b", 2, 3), ("c", 3, 2)]));
+        assert_eq!(names(&chain), ["b"]);
+        assert_eq!(
+            chain.issues(),
+            [
+                ChainIssue::Unused { name: "a".into() },
+                ChainIssue::Unused { name: "c".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn between_reports_missing_ends() {
+        let chain = PatchChain::between(1, 5, patches(&[("b", 2, 3), ("c", 3, 4)]));
+        assert_eq!(names(&chain), ["b", "c"]);
+        assert_eq!(
+            chain.issues(),
+            [
+                ChainIssue::Gap { from: 1, to: 2 },
+                ChainIssue::Gap { from: 4, to: 5 },
+            ]
+        );
+
+        let chain = PatchChain::between(2, 3, patches(&[("b", 2, 3), ("c", 3, 4)]));
+        assert_eq!(names(&chain), ["b"]);
+        assert_eq!(chain.issues(), [ChainIssue::Unused { name: "c".into() }]);
+    }
+}
@@ -0,0 +1,286 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::CrcReader;
+
+use crate::{
+    entry::normalize_path,
+    error::{IpfError, Result},
+    ies::IesTable,
+    ipf::{IpfArchive, IpfWriter},
+};
+
+/// Write a patch which turns `old` into `new`, to `writer`
+///
+/// Added and modified entries (see `IpfArchive::diff`) of `new` are copied as they are stored.
+/// The patch goes from `revision` of `old` to `revision` of `new`.
+pub fn create_patch<R, P, W>(old: &IpfArchive<R>, new: &mut IpfArchive<P>, writer: W) -> Result<W>
+where
+    R: Read + Seek,
+    P: Read + Seek,
+    W: Write + Seek,
+{
+    let diff = old.diff(new);
+    let mut changed: Vec<usize> = diff
+        .added()
+        .iter()
+        .chain(diff.modified().iter().map(|change| &change.new))
+        .map(|entry| entry.index())
+        .collect();
+    changed.sort_unstable();
+
+    let mut writer = IpfWriter::new(writer);
+    writer.set_base_revision(old.revision());
+    writer.set_revision(new.revision());
+    for index in changed {
+        writer.copy_entry(new, index)?;
+    }
+    writer.finish()
+}
+
+/// Write a patch which turns directory `old` into directory `new`, to `writer`
+///
+/// Directories are laid out like extracted archives, e.g. `xml.ipf/item.xml`
+/// is item.xml of archive xml.ipf. Files of `new` which `old` does not have,
+/// or has with another size or crc32, are added to the patch.
+///
+/// Files have to be extracted as they were before compression, i.e. with
+/// `ReadMode::Decompressed`. The default `ReadMode::Converted` (also used by `extract_to`)
+/// writes ies files as csv, so `.ies` files of `new` which are not ies tables are refused.
+pub fn create_patch_from_dirs<W: Write + Seek>(
+    old: impl AsRef<Path>,
+    new: impl AsRef<Path>,
+    base_revision: u32,
+    revision: u32,
+    writer: W,
+) -> Result<W> {
+    let old = old.as_ref();
+    let new = new.as_ref();
+    let old_files: HashMap<_, _> = list_files(old)?
+        .into_iter()
+        .map(|path| (normalize_path(&path), path))
+        .collect();
+
+    let mut writer = IpfWriter::new(writer);
+    writer.set_base_revision(base_revision);
+    writer.set_revision(revision);
+    for path in list_files(new)? {
+        if let Some(old_path) = old_files.get(&normalize_path(&path)) {
+            if same_content(&old.join(old_path), &new.join(&path))? {
+                continue;
+            }
+        }
+        let (archive_name, entry_path) = path.split_once('/').ok_or(IpfError::InvalidArchive(
+            "Files must be in a directory named after their archive, e.g. xml.ipf/item.xml",
+        ))?;
+        let mut file = BufReader::new(File::open(new.join(&path))?);
+        if is_ies(&path) && IesTable::parse(&mut file).is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{path} is not an ies table, extract with ReadMode::Decompressed"),
+            )
+            .into());
+        }
+        file.rewind()?;
+        writer.add_entry(archive_name, entry_path, file)?;
+    }
+    writer.finish()
+}
+
+fn is_ies(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("ies"))
+}
+
+/// Paths of every file under `root`, relative to it with `/` as separator, sorted
+fn list_files(root: &Path) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let path = dir.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let components = path
+                .components()
+                .map(|component| component.as_os_str().to_str())
+                .collect::<Option<Vec<_>>>()
+                .ok_or(IpfError::InvalidArchive("File name is not valid UTF-8"))?;
+            files.push(components.join("/"));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn same_content(old: &Path, new: &Path) -> Result<bool> {
+    if fs::metadata(old)?.len() != fs::metadata(new)?.len() {
+        return Ok(false);
+    }
+    let crc = |path: &Path| -> Result<u32> {
+        let mut reader = CrcReader::new(File::open(path)?);
+        io::copy(&mut reader, &mut io::sink())?;
+        Ok(reader.crc().sum())
+    };
+    Ok(crc(old)? == crc(new)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{Cursor, Read},
+        path::{Path, PathBuf},
+    };
+
+    use super::{create_patch, create_patch_from_dirs};
+    use crate::{
+        error::IpfError,
+        ies::{CsvOptions, IesColumn, IesTable},
+        ipf::{IpfArchive, IpfWriter},
+    };
+
+    fn archive(revision: u32, files: &[(&str, &str)]) -> IpfArchive<Cursor<Vec<u8>>> {
+        let mut writer = IpfWriter::new(Cursor::new(Vec::new()));
+        writer.set_revision(revision);
+        for (path, content) in files {
+            let (archive_name, path) = path.split_once('/').unwrap();
+            writer.add_bytes(archive_name, path, content).unwrap();
+        }
+        let mut cursor = writer.finish().unwrap();
+        cursor.set_position(0);
+        IpfArchive::new(cursor).unwrap()
+    }
+
+    /// `archive_name/path` and content of every entry
+    fn files<R: Read + std::io::Seek>(archive: &mut IpfArchive<R>) -> Vec<(String, String)> {
+        (0..archive.len())
+            .map(|index| {
+                let mut entry = archive.by_index(index).unwrap();
+                let mut content = String::new();
+                entry.read_to_string(&mut content).unwrap();
+                let path = entry.full_path().to_string_lossy().replace('\\', "/");
+                (path, content)
+            })
+            .collect()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("libtos-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn write_files(dir: &Path, files: &[(&str, &[u8])]) {
+        for (path, content) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+    }
+
+    fn ies() -> Vec<u8> {
+        let columns = vec![IesColumn::new("Name", true)];
+        let table = IesTable::from_csv(
+            "Name\nSword\n".as_bytes(),
+            "Item",
+            columns,
+            &CsvOptions::default(),
+        )
+        .unwrap();
+        let mut ies = Vec::new();
+        table.write_to(&mut ies).unwrap();
+        ies
+    }
+
+    #[test]
+    fn patch_of_archives_has_added_and_modified_entries() {
+        let old = archive(
+            1,
+            &[
+                ("xml.ipf/same.xml", "same"),
+                ("xml.ipf/item.xml", "old"),
+                ("xml.ipf/gone.xml", "x"),
+            ],
+        );
+        let mut new = archive(
+            2,
+            &[
+                ("xml.ipf/same.xml", "same"),
+                ("xml.ipf/item.xml", "new"),
+                ("ui.ipf/a.lua", "a"),
+            ],
+        );
+
+        let patch = create_patch(&old, &mut new, Cursor::new(Vec::new())).unwrap();
+        let mut patch = IpfArchive::new(Cursor::new(patch.into_inner())).unwrap();
+        assert_eq!((patch.base_revision(), patch.revision()), (1, 2));
+        assert_eq!(
+            files(&mut patch),
+            [
+                ("xml.ipf/item.xml".into(), "new".into()),
+                ("ui.ipf/a.lua".into(), "a".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn patch_of_dirs_has_added_and_modified_files() {
+        let dir = temp_dir("patch-of-dirs");
+        let ies = ies();
+        write_files(
+            &dir.join("old"),
+            &[("xml.ipf/same.xml", b"same"), ("xml.ipf/item.xml", b"old")],
+        );
+        write_files(
+            &dir.join("new"),
+            &[
+                ("xml.ipf/same.xml", b"same"),
+                ("xml.ipf/item.xml", b"new"),
+                ("ies.ipf/item.ies", &ies),
+            ],
+        );
+
+        let patch = create_patch_from_dirs(
+            dir.join("old"),
+            dir.join("new"),
+            1,
+            2,
+            Cursor::new(Vec::new()),
+        )
+        .unwrap();
+        let mut patch = IpfArchive::new(Cursor::new(patch.into_inner())).unwrap();
+        assert_eq!((patch.base_revision(), patch.revision()), (1, 2));
+        let paths: Vec<_> = files(&mut patch)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, ["ies.ipf/item.ies", "xml.ipf/item.xml"]);
+
+        // ies files extracted as csv
+        write_files(
+            &dir.join("new"),
+            &[("ies.ipf/item.ies", b"Name\r\nSword\r\n")],
+        );
+        let err = create_patch_from_dirs(
+            dir.join("old"),
+            dir.join("new"),
+            1,
+            2,
+            Cursor::new(Vec::new()),
+        )
+        .unwrap_err();
+        assert!(matches!(err, IpfError::Io(_)), "{err}");
+        assert!(err.to_string().contains("ies.ipf/item.ies"), "{err}");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
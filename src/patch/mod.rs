@@ -0,0 +1,7 @@
+mod apply;
+mod chain;
+mod create;
+
+pub use apply::apply_patches;
+pub use chain::{ChainIssue, PatchChain, PatchInfo};
+pub use create::{create_patch, create_patch_from_dirs};